@@ -1,90 +1,77 @@
 use super::*;
 
-/// only format tight cause it would not be supported to format breaking in code blocks
+/// breaking before operators is only supported in parenthesized, in code
+/// blocks a newline would end the expression.
 ///
-/// it could be supported in parenthesized.
-#[instrument(skip_all, ret)]
-pub(crate) fn format_bin_left_assoc(
-    parent: &LinkedNode,
-    children: &[String],
-    ctx: &mut Ctx,
-) -> String {
-    let res = format_bin_left_assoc_tight(parent, children, ctx);
-
-    if parent.kind() == Parenthesized
-        && (
-            crate::utils::max_line_length(&res) >= ctx.config.max_line_length
-            // || parent
-            // .children()
-            // .any(|x| x.kind() == LineComment || x.kind() == BlockComment)
-        )
-    {
-        return format_bin_left_assoc_breaking(parent, children, ctx);
-    }
-    res
-}
-
-/// not integrated and never used for now
+/// nested binary operations (`a + b + c`) share the group of the outermost one
+/// so they all break together.
 #[instrument(skip_all)]
-pub(crate) fn format_bin_left_assoc_breaking(
-    parent: &LinkedNode,
-    children: &[String],
-    ctx: &mut Ctx,
-) -> String {
-    let mut res = String::new();
-    for (s, node) in children.iter().zip(parent.children()) {
+pub(crate) fn format_bin_left_assoc(parent: &LinkedNode, children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    let breakable = is_in_parenthesized(parent);
+    let mut res = vec![];
+    let mut after_comment = false;
+    for (s, node) in children.into_iter().zip(parent.children()) {
         match node.kind() {
-            _ if ctx.off => res.push_str(node.text()),
+            _ if ctx.off => res.push(deep_no_format(&node)),
             x if BinOp::from_kind(x).is_some() => {
-                ctx.push_in("\n", &mut res);
-                ctx.push_raw_indent(s, &mut res);
+                // handles `not in` like a pro
+                if utils::prev_is_ignoring(&node, Not, &[Space]) {
+                    res.push(Doc::text(" "));
+                } else if !after_comment {
+                    res.push(separator(breakable));
+                }
+                res.push(s);
+                res.push(Doc::text(" "));
+                after_comment = false;
             }
-            LineComment | BlockComment => {
-                ctx.push_raw_indent(s, &mut res);
-                ctx.push_in("\n", &mut res);
+            Not => {
+                if !after_comment {
+                    res.push(separator(breakable));
+                }
+                res.push(s);
+                after_comment = false;
             }
             Space => {}
+            LineComment => {
+                if !matches!(res.last(), Some(Doc::Text(t)) if t.ends_with([' ', '\n'])) {
+                    res.push(Doc::text(" "));
+                }
+                res.push(s);
+                res.push(Doc::indent(Doc::HardLine));
+                after_comment = true;
+            }
             _ => {
-                ctx.push_in(s, &mut res);
+                res.push(s);
+                after_comment = false;
             }
         }
     }
-    res
+    ctx.lost_context();
+
+    if breakable && parent.parent_kind() != Some(Binary) {
+        Doc::group(Doc::Concat(res))
+    } else {
+        Doc::Concat(res)
+    }
 }
 
-#[instrument(skip_all)]
-pub(crate) fn format_bin_left_assoc_tight(
-    parent: &LinkedNode,
-    children: &[String],
-    ctx: &mut Ctx,
-) -> String {
-    let mut res = String::new();
-    for (s, node) in children.iter().zip(parent.children()) {
-        match node.kind() {
-            _ if ctx.off => res.push_str(node.text()),
+/// the space before an operator, a possible line break if we're in parenthesized.
+fn separator(breakable: bool) -> Doc {
+    if breakable {
+        Doc::indent(Doc::Line)
+    } else {
+        Doc::text(" ")
+    }
+}
 
-            x if BinOp::from_kind(x).is_some() => {
-                ctx.push_in(" ", &mut res);
-                ctx.push_raw_in(s, &mut res);
-                ctx.push_in(" ", &mut res);
-            }
-            // handles `not in` like a pro
-            Not => {
-                ctx.push_in(" ", &mut res);
-                ctx.push_raw_in(s, &mut res);
-            }
-            Space => {}
-            LineComment => {
-                ctx.push_in(" ", &mut res);
-                ctx.push_raw_in(s, &mut res);
-                ctx.push_in("\n", &mut res);
-                ctx.push_raw_in(&ctx.get_indent(), &mut res);
-                ctx.just_spaced = true;
-            }
-            _ => {
-                ctx.push_raw_in(s, &mut res);
-            }
+fn is_in_parenthesized(node: &LinkedNode) -> bool {
+    let mut node = node.clone();
+    while let Some(parent) = node.parent() {
+        match parent.kind() {
+            Binary => node = parent.clone(),
+            Parenthesized => return true,
+            _ => return false,
         }
     }
-    res
+    false
 }
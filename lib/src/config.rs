@@ -1,14 +1,133 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
 use serde::Deserialize;
 use serde::Serialize;
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
     pub indent_space: usize,
+    /// Whether the indentation is made of spaces or tabs, a tab counts as
+    /// `indent_space` columns.
+    pub indent_style: IndentStyle,
+    /// The line endings of the output, `auto` keeps the ones of the input.
+    pub newline_style: NewlineStyle,
     pub max_line_length: usize,
     /// If enabled, when breaking arguments, it will try to keep more on one line.
     pub experimental_args_breaking_consecutive: bool,
-    pub line_wrap: bool,
+    /// How the lines of markup are broken, see [LineWrap].
+    pub line_wrap: LineWrap,
+    /// If enabled, the nodes with syntax errors are left as they are.
+    pub keep_erroneous: bool,
+    /// If enabled, the output ends with a single newline, else the end of the
+    /// file is left as it is.
+    pub insert_final_newline: bool,
+    /// Globs of the files to format, all the typst files if empty.
+    pub include: Vec<String>,
+    /// Globs of the files to skip, even if they're included.
+    pub exclude: Vec<String>,
+    /// Settings for some of the files, applied in order, see [Config::for_file].
+    pub overrides: Vec<Override>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndentStyle {
+    #[default]
+    Space,
+    Tab,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NewlineStyle {
+    /// the ones of the first line of the input, `\n` if there is none.
+    #[default]
+    Auto,
+    Lf,
+    Crlf,
+    /// `\r\n` on windows, `\n` elsewhere.
+    Native,
+}
+
+/// How the lines of markup are broken, written `true` or `false` for the first
+/// ones in the config, the others by their name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "LineWrapToml", into = "LineWrapToml")]
+pub enum LineWrap {
+    /// the lines are left as they are.
+    Off,
+    /// the words are put on a line as long as they fit.
+    #[default]
+    Fill,
+    /// each sentence starts a new line, and is filled if too long.
+    Sentence,
+    /// the lines of a paragraph are joined, for editors wrapping them.
+    Unwrap,
+    /// the line breaks are kept, only the lines too long are broken.
+    Preserve,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum LineWrapToml {
+    Bool(bool),
+    Name(String),
+}
+
+impl TryFrom<LineWrapToml> for LineWrap {
+    type Error = String;
+
+    fn try_from(value: LineWrapToml) -> Result<Self, Self::Error> {
+        match value {
+            LineWrapToml::Bool(false) => Ok(LineWrap::Off),
+            LineWrapToml::Bool(true) => Ok(LineWrap::Fill),
+            LineWrapToml::Name(name) => match name.as_str() {
+                "sentence" => Ok(LineWrap::Sentence),
+                "unwrap" => Ok(LineWrap::Unwrap),
+                "preserve" => Ok(LineWrap::Preserve),
+                _ => Err(format!(
+                    "unknown line_wrap {name:?}, expected true, false, \"sentence\", \"unwrap\" or \"preserve\""
+                )),
+            },
+        }
+    }
+}
+
+impl From<LineWrap> for LineWrapToml {
+    fn from(value: LineWrap) -> Self {
+        match value {
+            LineWrap::Off => LineWrapToml::Bool(false),
+            LineWrap::Fill => LineWrapToml::Bool(true),
+            LineWrap::Sentence => LineWrapToml::Name("sentence".to_owned()),
+            LineWrap::Unwrap => LineWrapToml::Name("unwrap".to_owned()),
+            LineWrap::Preserve => LineWrapToml::Name("preserve".to_owned()),
+        }
+    }
+}
+
+/// The settings to change for the files matching one of the globs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Override {
+    pub files: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indent_space: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indent_style: Option<IndentStyle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub newline_style: Option<NewlineStyle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_line_length: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub experimental_args_breaking_consecutive: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_wrap: Option<LineWrap>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_erroneous: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insert_final_newline: Option<bool>,
 }
 
 impl Default for Config {
@@ -16,9 +135,16 @@ impl Default for Config {
         Self {
             // this being strictly > to 1 is assumed.
             indent_space: 2,
+            indent_style: IndentStyle::Space,
+            newline_style: NewlineStyle::Auto,
             max_line_length: 80,
-            line_wrap: true,
+            line_wrap: LineWrap::Fill,
             experimental_args_breaking_consecutive: false,
+            keep_erroneous: false,
+            insert_final_newline: false,
+            include: vec![],
+            exclude: vec![],
+            overrides: vec![],
         }
     }
 }
@@ -28,7 +154,128 @@ impl Config {
         toml::from_str(s).map_err(|e| e.message().to_string())
     }
 
+    /// Builds the config from layers of toml, named by where they come from,
+    /// each one overrides the keys it sets in the ones before, the first one
+    /// overrides the defaults.
+    ///
+    /// Returns with it the name of the layer each key comes from, `default` if
+    /// none set it.
+    pub fn from_toml_layers(
+        layers: &[(String, String)],
+    ) -> Result<(Self, BTreeMap<String, String>), String> {
+        let toml::Value::Table(mut table) = toml::Value::try_from(Self::default()).unwrap() else {
+            unreachable!("the config is a table");
+        };
+        let mut origins: BTreeMap<String, String> = table
+            .keys()
+            .map(|key| (key.clone(), "default".to_string()))
+            .collect();
+        for (name, s) in layers {
+            // checks the keys and the types of the values.
+            Self::from_toml(s).map_err(|e| format!("{name}: {e}"))?;
+            let layer: toml::Table = toml::from_str(s).map_err(|e| format!("{name}: {e}"))?;
+            for (key, value) in layer {
+                origins.insert(key.clone(), name.clone());
+                table.insert(key, value);
+            }
+        }
+        let config = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e: toml::de::Error| e.message().to_string())?;
+        Ok((config, origins))
+    }
+
     pub fn default_toml() -> String {
         toml::to_string_pretty(&Self::default()).unwrap()
     }
+
+    /// true if the path matches one of the include globs and none of the
+    /// exclude ones, it should be relative to the directory of the config.
+    ///
+    /// A glob matches the path or its end, `vendor/**` matches `a/vendor/b.typ`.
+    pub fn includes(&self, path: &Path) -> Result<bool, String> {
+        Ok((self.include.is_empty() || matches(&self.include, path)?)
+            && !matches(&self.exclude, path)?)
+    }
+
+    /// the overrides whose globs match the path, it should be relative to the
+    /// directory of the config.
+    pub fn overrides_for(&self, path: &Path) -> Result<Vec<&Override>, String> {
+        let mut res = vec![];
+        for o in &self.overrides {
+            if matches(&o.files, path)? {
+                res.push(o);
+            }
+        }
+        Ok(res)
+    }
+
+    /// the config for a file, with the settings of the overrides matching it,
+    /// the path should be relative to the directory of the config.
+    pub fn for_file(&self, path: &Path) -> Result<Config, String> {
+        let mut config = self.clone();
+        for o in self.overrides_for(path)? {
+            o.apply(&mut config);
+        }
+        Ok(config)
+    }
+}
+
+impl Override {
+    pub fn apply(&self, config: &mut Config) {
+        let Override {
+            files: _,
+            indent_space,
+            indent_style,
+            newline_style,
+            max_line_length,
+            experimental_args_breaking_consecutive,
+            line_wrap,
+            keep_erroneous,
+            insert_final_newline,
+        } = self.clone();
+        config.indent_space = indent_space.unwrap_or(config.indent_space);
+        config.indent_style = indent_style.unwrap_or(config.indent_style);
+        config.newline_style = newline_style.unwrap_or(config.newline_style);
+        config.max_line_length = max_line_length.unwrap_or(config.max_line_length);
+        config.experimental_args_breaking_consecutive = experimental_args_breaking_consecutive
+            .unwrap_or(config.experimental_args_breaking_consecutive);
+        config.line_wrap = line_wrap.unwrap_or(config.line_wrap);
+        config.keep_erroneous = keep_erroneous.unwrap_or(config.keep_erroneous);
+        config.insert_final_newline = insert_final_newline.unwrap_or(config.insert_final_newline);
+    }
+}
+
+impl NewlineStyle {
+    /// the line ending to write for the source `s`.
+    pub fn newline(self, s: &str) -> &'static str {
+        let crlf = match self {
+            NewlineStyle::Auto => s.find('\n').is_some_and(|idx| s[..idx].ends_with('\r')),
+            NewlineStyle::Lf => false,
+            NewlineStyle::Crlf => true,
+            NewlineStyle::Native => cfg!(windows),
+        };
+        if crlf {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+}
+
+/// true if one of the globs matches the path or its end.
+fn matches(globs: &[String], path: &Path) -> Result<bool, String> {
+    // `./a.typ` should match `a.typ`.
+    let path: &Path = path
+        .strip_prefix(Component::CurDir.as_os_str())
+        .unwrap_or(path);
+    for glob in globs {
+        if globmatch::Builder::new(glob)
+            .build_glob_set()?
+            .is_match(path)
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
 }
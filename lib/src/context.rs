@@ -60,30 +60,6 @@ impl Ctx {
         }
     }
 
-    /// makes the context aware it missed info,
-    /// should be called when pushing directly in result.
-    pub(crate) fn push_raw_in(&mut self, s: &str, result: &mut String) {
-        result.push_str(s);
-        self.lost_context();
-    }
-
-    /// adds an indentation for each line the input except the first to match the current level of indentation.
-    pub(crate) fn push_raw_indent(&mut self, s: &str, result: &mut String) {
-        let mut is_first = true;
-        for s in s.split('\n') {
-            if is_first {
-                is_first = false;
-                self.push_raw_in(s, result);
-                continue;
-            }
-            self.push_raw_in("\n", result);
-            self.push_raw_in(
-                format!("{}{}", self.get_indent(), s).trim_end_matches(' '),
-                result,
-            );
-        }
-    }
-
     // pub(crate) fn push_in_indent(&mut self, s: &str, result: &mut String) {
     //     let mut is_first = true;
     //     for s in s.lines() {
@@ -107,9 +83,4 @@ impl Ctx {
         self.just_spaced = false;
         self.consec_new_line = 0;
     }
-
-    /// returns an indent using config to get it's length.
-    pub(crate) fn get_indent(&self) -> String {
-        " ".repeat(self.config.indent_space)
-    }
 }
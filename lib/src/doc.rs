@@ -0,0 +1,421 @@
+use super::*;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The layout the formatters produce, in the spirit of Wadler's "prettier printer".
+///
+/// Formatters don't decide where lines break anymore, they describe where they
+/// *could* break using [Doc::Line] and [Doc::SoftLine] inside a [Doc::Group],
+/// the [print]er then picks the breaks knowing the column it's at.
+#[derive(Debug, Clone)]
+pub(crate) enum Doc {
+    /// Some text, every line after the first one gets indented to the current level.
+    Text(String),
+    /// Some text printed exactly as is, for raw blocks and disabled regions.
+    Verbatim(String),
+    /// A space if the enclosing group is flat, a newline otherwise.
+    Line,
+    /// Nothing if the enclosing group is flat, a newline otherwise.
+    SoftLine,
+    /// Always a newline.
+    HardLine,
+    Concat(Vec<Doc>),
+    /// Newlines inside are followed by one more level of indentation.
+    Indent(Box<Doc>),
+    /// Like [Doc::Indent] but only if the enclosing group is broken.
+    IndentIfBreak(Box<Doc>),
+    /// Printed flat if it fits on the line and `should_break` is false.
+    Group {
+        doc: Box<Doc>,
+        should_break: bool,
+    },
+    /// Picks the first doc if the enclosing group is broken, the second otherwise.
+    IfBreak(Box<Doc>, Box<Doc>),
+    /// Alternating content and separators, separators only break when the next
+    /// content doesn't fit on the line, used to wrap words.
+    Fill(Vec<Doc>),
+}
+
+impl Doc {
+    pub(crate) fn text(s: impl Into<String>) -> Self {
+        Doc::Text(s.into())
+    }
+
+    pub(crate) fn verbatim(s: impl Into<String>) -> Self {
+        Doc::Verbatim(s.into())
+    }
+
+    pub(crate) fn nil() -> Self {
+        Doc::Concat(vec![])
+    }
+
+    pub(crate) fn indent(doc: Doc) -> Self {
+        Doc::Indent(Box::new(doc))
+    }
+
+    pub(crate) fn indent_if_break(doc: Doc) -> Self {
+        Doc::IndentIfBreak(Box::new(doc))
+    }
+
+    pub(crate) fn group(doc: Doc) -> Self {
+        Doc::Group {
+            doc: Box::new(doc),
+            should_break: false,
+        }
+    }
+
+    pub(crate) fn group_breaking(doc: Doc, should_break: bool) -> Self {
+        Doc::Group {
+            doc: Box::new(doc),
+            should_break,
+        }
+    }
+
+    pub(crate) fn if_break(broken: Doc, flat: Doc) -> Self {
+        Doc::IfBreak(Box::new(broken), Box::new(flat))
+    }
+
+    /// true if this will contain a newline whatever the printer decides.
+    pub(crate) fn has_line_break(&self) -> bool {
+        match self {
+            Doc::Text(s) | Doc::Verbatim(s) => s.contains('\n'),
+            Doc::Line | Doc::SoftLine => false,
+            Doc::HardLine => true,
+            Doc::Concat(docs) | Doc::Fill(docs) => docs.iter().any(Doc::has_line_break),
+            Doc::Indent(doc) | Doc::IndentIfBreak(doc) => doc.has_line_break(),
+            Doc::Group { doc, should_break } => *should_break || doc.has_line_break(),
+            Doc::IfBreak(_, flat) => flat.has_line_break(),
+        }
+    }
+
+    /// the width of this if printed on one line, None if it can't be.
+    pub(crate) fn flat_width(&self) -> Option<usize> {
+        match self {
+            Doc::Text(s) | Doc::Verbatim(s) => (!s.contains('\n')).then(|| width(s)),
+            Doc::Line => Some(1),
+            Doc::SoftLine => Some(0),
+            Doc::HardLine => None,
+            Doc::Concat(docs) | Doc::Fill(docs) => docs.iter().map(Doc::flat_width).sum(),
+            Doc::Indent(doc) | Doc::IndentIfBreak(doc) => doc.flat_width(),
+            Doc::Group {
+                should_break: true, ..
+            } => None,
+            Doc::Group { doc, .. } => doc.flat_width(),
+            Doc::IfBreak(_, flat) => flat.flat_width(),
+        }
+    }
+
+    /// true if this prints nothing but whitespace.
+    fn is_blank(&self) -> bool {
+        match self {
+            Doc::Text(s) => s.chars().all(|c| c == ' ' || c == '\n'),
+            Doc::Verbatim(s) => s.is_empty(),
+            Doc::Line | Doc::SoftLine | Doc::HardLine => true,
+            Doc::Concat(docs) | Doc::Fill(docs) => docs.iter().all(Doc::is_blank),
+            Doc::Indent(doc) | Doc::IndentIfBreak(doc) | Doc::Group { doc, .. } => doc.is_blank(),
+            Doc::IfBreak(broken, flat) => broken.is_blank() && flat.is_blank(),
+        }
+    }
+
+    /// removes the trailing spaces, newlines and lines, going back before them.
+    pub(crate) fn trim_end(&mut self) {
+        match self {
+            Doc::Text(s) => s.truncate(s.trim_end_matches([' ', '\n']).len()),
+            Doc::Line | Doc::SoftLine | Doc::HardLine => *self = Doc::nil(),
+            Doc::Concat(docs) | Doc::Fill(docs) => trim_end(docs),
+            Doc::Indent(doc) | Doc::IndentIfBreak(doc) | Doc::Group { doc, .. } => doc.trim_end(),
+            Doc::Verbatim(_) | Doc::IfBreak(..) => {}
+        }
+    }
+}
+
+/// removes the trailing spaces, newlines and lines from a list of docs,
+/// like rewinding a string to its last non blank character.
+pub(crate) fn trim_end(docs: &mut Vec<Doc>) {
+    while docs.last().is_some_and(Doc::is_blank) {
+        docs.pop();
+    }
+    if let Some(last) = docs.last_mut() {
+        last.trim_end();
+    }
+}
+
+/// the columns taken by the text, two for the wide characters of CJK text.
+pub(crate) fn width(s: &str) -> usize {
+    s.graphemes(true)
+        .map(|g| match g.chars().next() {
+            Some(c) if crate::linebreak::is_wide(c) => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Cmd<'a> {
+    Doc(&'a Doc),
+    /// what's left to print of a [Doc::Fill].
+    Fill(&'a [Doc]),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Item<'a> {
+    indent: usize,
+    mode: Mode,
+    cmd: Cmd<'a>,
+}
+
+struct Printer<'a> {
+    config: &'a Config,
+    out: String,
+    col: usize,
+    /// true after a newline, the indentation is only pushed with the next
+    /// text so blank lines stay empty.
+    line_start: bool,
+    /// the indentation of the line we're at the start of, if it was decided by a
+    /// line doc, otherwise the doc printing the next text decides.
+    line_indent: Option<usize>,
+    /// set when a newline was printed in a flat group, the groups after it
+    /// have to check again if they fit.
+    remeasure: bool,
+    /// the columns every line but the first is indented with, before the indent
+    /// of the docs.
+    base_indent: usize,
+    /// where the last verbatim text ends in the output, the trailing spaces
+    /// before it are content.
+    verbatim_end: usize,
+}
+
+/// Prints the doc choosing for each group, from the outermost one, if it
+/// can stay flat or must break to not go over `max_line_length`.
+#[instrument(skip_all)]
+pub(crate) fn print(doc: &Doc, config: &Config) -> String {
+    print_at(doc, config, 0, 0)
+}
+
+/// Prints a doc that doesn't start the file, the first line starts at `col`
+/// and the following ones are indented by at least `base_indent` columns.
+#[instrument(skip_all)]
+pub(crate) fn print_at(doc: &Doc, config: &Config, col: usize, base_indent: usize) -> String {
+    let mut printer = Printer {
+        config,
+        out: String::new(),
+        col,
+        line_start: false,
+        line_indent: None,
+        remeasure: false,
+        base_indent,
+        verbatim_end: 0,
+    };
+    printer.print(doc);
+    printer.out
+}
+
+impl<'a> Printer<'a> {
+    fn indent_width(&self, indent: usize) -> usize {
+        self.base_indent + indent * self.config.indent_space
+    }
+
+    fn print(&mut self, doc: &'a Doc) {
+        let mut stack = vec![Item {
+            indent: 0,
+            mode: Mode::Break,
+            cmd: Cmd::Doc(doc),
+        }];
+        while let Some(item) = stack.pop() {
+            let Item { indent, mode, cmd } = item;
+            let doc = match cmd {
+                Cmd::Doc(doc) => doc,
+                Cmd::Fill(parts) => {
+                    self.print_fill(item, parts, &mut stack);
+                    continue;
+                }
+            };
+            let at = |indent, mode, doc| Item {
+                indent,
+                mode,
+                cmd: Cmd::Doc(doc),
+            };
+            match doc {
+                Doc::Text(s) => self.push_text(s, indent),
+                Doc::Verbatim(s) => self.push_verbatim(s, indent),
+                Doc::Line if mode == Mode::Flat => self.push_text(" ", indent),
+                Doc::SoftLine if mode == Mode::Flat => {}
+                Doc::Line | Doc::SoftLine | Doc::HardLine => self.push_newline(Some(indent)),
+                Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|d| at(indent, mode, d))),
+                Doc::Indent(doc) => stack.push(at(indent + 1, mode, doc)),
+                Doc::IndentIfBreak(doc) if mode == Mode::Break => {
+                    stack.push(at(indent + 1, mode, doc))
+                }
+                Doc::IndentIfBreak(doc) => stack.push(at(indent, mode, doc)),
+                Doc::Group { doc, should_break } => {
+                    let mode = if *should_break {
+                        Mode::Break
+                    } else if mode == Mode::Flat && !self.remeasure {
+                        Mode::Flat
+                    } else {
+                        self.remeasure = false;
+                        if self.fits(indent, vec![(Mode::Flat, Cmd::Doc(doc))], &stack) {
+                            Mode::Flat
+                        } else {
+                            Mode::Break
+                        }
+                    };
+                    stack.push(at(indent, mode, doc));
+                }
+                Doc::IfBreak(broken, _) if mode == Mode::Break => {
+                    stack.push(at(indent, mode, broken))
+                }
+                Doc::IfBreak(_, flat) => stack.push(at(indent, mode, flat)),
+                Doc::Fill(parts) => stack.push(Item {
+                    indent,
+                    mode,
+                    cmd: Cmd::Fill(parts),
+                }),
+            }
+        }
+    }
+
+    /// prints the first content of the fill, then the separator flat if the
+    /// next content fits on the line and broken otherwise.
+    fn print_fill(&mut self, item: Item<'a>, parts: &'a [Doc], stack: &mut Vec<Item<'a>>) {
+        let at = |mode, doc| Item {
+            indent: item.indent,
+            mode,
+            cmd: Cmd::Doc(doc),
+        };
+        let Some(content) = parts.first() else {
+            return;
+        };
+        let content_mode = if self.fits(item.indent, vec![(Mode::Flat, Cmd::Doc(content))], &[]) {
+            Mode::Flat
+        } else {
+            Mode::Break
+        };
+        let [_, separator, next, ..] = parts else {
+            stack.push(at(content_mode, content));
+            return;
+        };
+        let two = [next, separator, content].map(|d| (Mode::Flat, Cmd::Doc(d)));
+        let separator_mode = if self.fits(item.indent, two.to_vec(), &[]) {
+            Mode::Flat
+        } else {
+            Mode::Break
+        };
+        stack.push(Item {
+            cmd: Cmd::Fill(&parts[2..]),
+            ..item
+        });
+        stack.push(at(separator_mode, separator));
+        stack.push(at(content_mode, content));
+    }
+
+    /// true if the docs on the stack, followed by the rest up to its next
+    /// possible line break, don't go over the max line length.
+    fn fits(&self, indent: usize, mut stack: Vec<(Mode, Cmd<'a>)>, rest: &[Item<'a>]) -> bool {
+        let mut remaining = self.config.max_line_length as isize - self.col as isize;
+        if self.line_start {
+            remaining -= self.indent_width(self.line_indent.unwrap_or(indent)) as isize;
+        }
+        let mut rest = rest.iter().rev();
+        loop {
+            if remaining < 0 {
+                return false;
+            }
+            let Some((mode, cmd)) = stack.pop().or_else(|| rest.next().map(|i| (i.mode, i.cmd)))
+            else {
+                return true;
+            };
+            let doc = match cmd {
+                Cmd::Doc(doc) => doc,
+                Cmd::Fill(parts) => {
+                    stack.extend(parts.iter().rev().map(|d| (mode, Cmd::Doc(d))));
+                    continue;
+                }
+            };
+            match doc {
+                Doc::Text(s) | Doc::Verbatim(s) => match s.split_once('\n') {
+                    Some((first, _)) => return remaining >= width(first) as isize,
+                    None => remaining -= width(s) as isize,
+                },
+                Doc::Line | Doc::SoftLine if mode == Mode::Break => return true,
+                Doc::HardLine => return true,
+                Doc::Line => remaining -= 1,
+                Doc::SoftLine => {}
+                Doc::Concat(docs) | Doc::Fill(docs) => {
+                    stack.extend(docs.iter().rev().map(|d| (mode, Cmd::Doc(d))))
+                }
+                Doc::Indent(doc) | Doc::IndentIfBreak(doc) => stack.push((mode, Cmd::Doc(doc))),
+                Doc::Group { doc, should_break } => {
+                    let mode = if *should_break { Mode::Break } else { mode };
+                    stack.push((mode, Cmd::Doc(doc)));
+                }
+                Doc::IfBreak(broken, _) if mode == Mode::Break => {
+                    stack.push((mode, Cmd::Doc(broken)))
+                }
+                Doc::IfBreak(_, flat) => stack.push((mode, Cmd::Doc(flat))),
+            }
+        }
+    }
+
+    fn flush_indent(&mut self, indent: usize) {
+        if self.line_start {
+            self.line_start = false;
+            let indent = self.line_indent.take().unwrap_or(indent);
+            let columns = self.indent_width(indent);
+            self.col += columns;
+            match self.config.indent_style {
+                IndentStyle::Space => self.out.push_str(&" ".repeat(columns)),
+                IndentStyle::Tab => {
+                    let tab_width = self.config.indent_space.max(1);
+                    self.out.push_str(&"\t".repeat(columns / tab_width));
+                    self.out.push_str(&" ".repeat(columns % tab_width));
+                }
+            }
+        }
+    }
+
+    /// ends the line, removing its trailing spaces.
+    fn push_newline(&mut self, indent: Option<usize>) {
+        let end = self.out.trim_end_matches(' ').len().max(self.verbatim_end);
+        self.out.truncate(end);
+        self.out.push('\n');
+        self.col = 0;
+        self.line_start = true;
+        self.line_indent = indent;
+        self.remeasure = true;
+    }
+
+    fn push_text(&mut self, s: &str, indent: usize) {
+        for (idx, line) in s.split('\n').enumerate() {
+            if idx != 0 {
+                self.push_newline(None);
+            }
+            if !line.is_empty() {
+                self.flush_indent(indent);
+                self.col += width(line);
+                self.out.push_str(line);
+            }
+        }
+    }
+
+    fn push_verbatim(&mut self, s: &str, indent: usize) {
+        if s.is_empty() {
+            return;
+        }
+        self.flush_indent(indent);
+        self.out.push_str(s);
+        self.verbatim_end = self.out.len();
+        match s.rsplit_once('\n') {
+            Some((_, last)) => {
+                self.col = width(last);
+                self.remeasure = true;
+            }
+            None => self.col += width(s),
+        }
+    }
+}
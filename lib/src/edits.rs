@@ -0,0 +1,61 @@
+use std::ops::Range;
+
+use similar::{DiffOp, TextDiff};
+
+use super::*;
+
+/// Formats the source and returns the edits turning it into the formatted one,
+/// the ranges are byte offsets in `s`, sorted and not overlapping.
+///
+/// Only what changed is replaced, so editors can keep the cursor and marks
+/// where they were.
+#[must_use]
+pub fn format_edits(s: &str, config: Config) -> Vec<(Range<usize>, String)> {
+    edits(s, &format(s, config))
+}
+
+/// the edits turning `old` into `new`, the lines are compared first then the
+/// characters of the lines that changed.
+pub(crate) fn edits(old: &str, new: &str) -> Vec<(Range<usize>, String)> {
+    let mut res: Vec<(Range<usize>, String)> = vec![];
+    let lines = TextDiff::from_lines(old, new);
+    let old_lines = offsets(lines.old_slices());
+    let new_lines = offsets(lines.new_slices());
+    for op in lines.ops() {
+        if let DiffOp::Equal { .. } = op {
+            continue;
+        }
+        let old_hunk = old_lines[op.old_range().start]..old_lines[op.old_range().end];
+        let new_hunk = new_lines[op.new_range().start]..new_lines[op.new_range().end];
+        let chars = TextDiff::from_chars(&old[old_hunk.clone()], &new[new_hunk.clone()]);
+        let old_chars = offsets(chars.old_slices());
+        let new_chars = offsets(chars.new_slices());
+        for op in chars.ops() {
+            if let DiffOp::Equal { .. } = op {
+                continue;
+            }
+            let range = old_hunk.start + old_chars[op.old_range().start]
+                ..old_hunk.start + old_chars[op.old_range().end];
+            let text = &new[new_hunk.start + new_chars[op.new_range().start]
+                ..new_hunk.start + new_chars[op.new_range().end]];
+            // a deletion followed by an insertion is one replacement.
+            match res.last_mut() {
+                Some((last, last_text)) if last.end == range.start => {
+                    last.end = range.end;
+                    last_text.push_str(text);
+                }
+                _ => res.push((range, text.to_string())),
+            }
+        }
+    }
+    res
+}
+
+/// the byte offset where each slice starts, followed by the total length.
+fn offsets(slices: &[&str]) -> Vec<usize> {
+    let mut res = vec![0];
+    for slice in slices {
+        res.push(res.last().unwrap() + slice.len());
+    }
+    res
+}
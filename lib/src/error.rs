@@ -0,0 +1,70 @@
+use std::fmt;
+
+use super::*;
+
+/// Returned by [try_format] when the source doesn't parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatError {
+    pub errors: Vec<ParseError>,
+}
+
+/// A syntax error, the line and column start at 1, the column counts chars.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, error) in self.errors.iter().enumerate() {
+            if idx != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Formats the source only if it parses without errors, the formatting of a
+/// document with errors could change its meaning once they're fixed.
+pub fn try_format(s: &str, config: Config) -> Result<String, FormatError> {
+    let init = parse(s);
+    let errors = parse_errors(s, &LinkedNode::new(&init));
+    if !errors.is_empty() {
+        return Err(FormatError { errors });
+    }
+    Ok(format(s, config))
+}
+
+fn parse_errors(s: &str, node: &LinkedNode) -> Vec<ParseError> {
+    if !node.erroneous() {
+        return vec![];
+    }
+    if node.kind() == Error {
+        let before = &s[..node.offset()];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        return node
+            .errors()
+            .into_iter()
+            .map(|error| ParseError {
+                line: before.matches('\n').count() + 1,
+                column: before[line_start..].chars().count() + 1,
+                message: error.message.to_string(),
+            })
+            .collect();
+    }
+    node.children()
+        .flat_map(|child| parse_errors(s, &child))
+        .collect()
+}
@@ -12,63 +12,128 @@ use typst_syntax::{parse, LinkedNode};
 use Option::None;
 
 mod config;
-pub use config::Config;
+pub use config::{Config, IndentStyle, LineWrap, NewlineStyle, Override};
 mod context;
 use context::Ctx;
+mod doc;
+use doc::Doc;
+mod edits;
+pub use edits::format_edits;
+mod error;
+pub use error::{try_format, FormatError, ParseError};
 
+mod linebreak;
 mod utils;
 
 mod binary;
 mod code_blocks;
 mod markup;
 mod params;
+mod range;
+pub use range::format_range;
+mod verify;
+pub use verify::verify;
+
+const BOM: &str = "\u{feff}";
 
 #[must_use]
 pub fn format(s: &str, config: Config) -> String {
-    //replace tabs
-    let s = &s.replace('\t', &" ".repeat(config.indent_space));
+    // the byte order mark is kept as is, it isn't part of the document.
+    let (bom, s) = match s.strip_prefix(BOM) {
+        Some(s) => (BOM, s),
+        None => ("", s),
+    };
+    let newline = config.newline_style.newline(s);
+    let s = s.replace("\r\n", "\n");
+    let (s, _) = &expand_tabs(&s, config.indent_space);
 
     let init = parse(s);
     let mut context = Ctx::from_config(config);
     let root = LinkedNode::new(&init);
-    let s = visit(&root, &mut context);
-    regex::Regex::new("( )+\n")
-        .unwrap()
-        .replace_all(&s, "\n")
-        .to_string()
+    let doc = visit(&root, &mut context);
+    let s = doc::print(&doc, &context.config);
+    let s = if context.config.insert_final_newline {
+        format!("{}\n", s.trim_end_matches('\n'))
+    } else {
+        s
+    };
+    format!("{bom}{}", s.replace('\n', newline))
+}
+
+/// Replaces the tabs with `indent_space` spaces, except in strings, raw text
+/// and comments where they're content.
+///
+/// Returns with it the offsets of the replaced tabs in `s`.
+fn expand_tabs(s: &str, indent_space: usize) -> (String, Vec<usize>) {
+    fn walk(node: &LinkedNode, s: &str, spaces: &str, res: &mut (String, Vec<usize>)) {
+        let text = &s[node.range()];
+        if [Str, Raw, LineComment, BlockComment].contains(&node.kind()) {
+            res.0.push_str(text);
+        } else if node.children().len() == 0 {
+            for (idx, c) in text.char_indices() {
+                if c == '\t' {
+                    res.0.push_str(spaces);
+                    res.1.push(node.offset() + idx);
+                } else {
+                    res.0.push(c);
+                }
+            }
+        } else {
+            for child in node.children() {
+                walk(&child, s, spaces, res);
+            }
+        }
+    }
+    if !s.contains('\t') {
+        return (s.to_string(), vec![]);
+    }
+    let init = parse(s);
+    let mut res = (String::new(), vec![]);
+    walk(
+        &LinkedNode::new(&init),
+        s,
+        &" ".repeat(indent_space),
+        &mut res,
+    );
+    res
 }
 
 /// This is recursively called on the AST, the formatting is bottom up,
-/// nodes will decide based on the size of their children and the max line length
-/// how they will be formatted.
+/// nodes build a [Doc] out of the docs of their children, describing where
+/// lines may break, the printer then decides based on the max line length.
 ///
 /// One assumed rule is that no kind should be formatting with surrounded space
 #[instrument(skip_all,name = "V", fields(kind = format!("{:?}",node.kind())))]
-fn visit(node: &LinkedNode, ctx: &mut Ctx) -> String {
-    let mut res: Vec<String> = vec![];
+fn visit(node: &LinkedNode, ctx: &mut Ctx) -> Doc {
+    if ctx.config.keep_erroneous && is_erroneous(node) {
+        ctx.lost_context();
+        return deep_no_format(node);
+    }
+    let mut res: Vec<Doc> = vec![];
     for child in node.children() {
         let child_fmt = visit(&child, ctx);
         res.push(child_fmt);
     }
     let res = match node.kind() {
         LineComment => format_comment_handling_disable(node, &res, ctx),
-        _ if ctx.off => no_format(node, &res, ctx),
-        Binary => binary::format_bin_left_assoc(node, &res, ctx),
-        Named | Keyed => format_named_args(node, &res, ctx),
-        ListItem | EnumItem | TermItem => format_list_enum(node, &res, ctx),
-        CodeBlock => code_blocks::format_code_blocks(node, &res, ctx),
-        Markup => markup::format_markup(node, &res, ctx),
-        ContentBlock => markup::format_content_blocks(node, &res, ctx),
+        _ if ctx.off => no_format(node, res, ctx),
+        Binary => binary::format_bin_left_assoc(node, res, ctx),
+        Named | Keyed => format_named_args(node, res, ctx),
+        ListItem | EnumItem | TermItem => format_list_enum(node, res, ctx),
+        CodeBlock => code_blocks::format_code_blocks(node, res, ctx),
+        Markup => markup::format_markup(node, res, ctx),
+        ContentBlock => markup::format_content_blocks(node, res, ctx),
         Args | Params | Dict | Array | Destructuring | Parenthesized => {
-            params::format_args(node, &res, ctx)
+            params::format_args(node, res, ctx)
         }
-        LetBinding => format_let_binding(node, &res, ctx),
-        Conditional => conditional_format(node, &res, ctx),
-        Raw | BlockComment => {
+        LetBinding => format_let_binding(node, res, ctx),
+        Conditional => conditional_format(node, res, ctx),
+        // their newlines are content, they mustn't be indented.
+        Raw | Str | BlockComment => {
             ctx.lost_context();
-            node.text().to_string()
+            Doc::verbatim(node.text().as_str())
         }
-        _ => format_default(node, &res, ctx),
+        _ => format_default(node, res, ctx),
     };
     if node.children().count() == 0 {
         debug!("TOKEN : {:?}", node.kind());
@@ -86,108 +151,109 @@ fn visit(node: &LinkedNode, ctx: &mut Ctx) -> String {
 /// - putting more than two consecutive newlines.
 ///
 /// For the already formatted children, change nothing.
-#[instrument(skip_all, ret)]
-fn format_default(node: &LinkedNode, children: &[String], ctx: &mut Ctx) -> String {
-    let mut res = String::new();
-    ctx.push_in(node.text(), &mut res);
-    for s in children {
-        ctx.push_raw_in(s, &mut res);
+#[instrument(skip_all)]
+fn format_default(node: &LinkedNode, mut children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    let mut text = String::new();
+    ctx.push_in(node.text(), &mut text);
+    if children.is_empty() {
+        return Doc::Text(text);
     }
-    res
+    ctx.lost_context();
+    children.insert(0, Doc::Text(text));
+    Doc::Concat(children)
 }
 
-fn no_format(parent: &LinkedNode, children: &[String], ctx: &mut Ctx) -> String {
-    let mut res = String::new();
-    ctx.push_raw_in(parent.text(), &mut res);
-    for s in children {
-        ctx.push_raw_in(s, &mut res);
-    }
-    res
+fn no_format(parent: &LinkedNode, mut children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    ctx.lost_context();
+    children.insert(0, Doc::verbatim(parent.text().as_str()));
+    Doc::Concat(children)
 }
 
-fn deep_no_format(parent: &LinkedNode) -> String {
-    let mut res: Vec<String> = vec![];
-    for child in parent.children() {
-        let child_fmt = deep_no_format(&child);
-        res.push(child_fmt);
+/// true for the errors and the nodes directly containing one, except markup and
+/// code where only the error is kept, so the rest of the document is formatted.
+fn is_erroneous(node: &LinkedNode) -> bool {
+    match node.kind() {
+        Error => true,
+        Markup | Code => false,
+        _ => node.children().any(|child| child.kind() == Error),
     }
-    no_format(parent, &res, &mut Ctx::default())
 }
 
-fn conditional_format(parent: &LinkedNode, children: &[String], ctx: &mut Ctx) -> String {
-    let mut res = String::new();
-    ctx.push_raw_in(parent.text(), &mut res);
-    for (s, node) in children.iter().zip(parent.children()) {
+/// the text of the node and all its children, untouched.
+fn deep_no_format(parent: &LinkedNode) -> Doc {
+    Doc::verbatim(parent.get().clone().into_text().as_str())
+}
+
+fn conditional_format(parent: &LinkedNode, children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    let mut res = vec![];
+    for (s, node) in children.into_iter().zip(parent.children()) {
         match node.kind() {
-            _ if ctx.off => res.push_str(node.text()),
+            _ if ctx.off => res.push(deep_no_format(&node)),
             Space => {}
             If => {
-                ctx.push_raw_in(s, &mut res);
-                ctx.push_raw_in(" ", &mut res);
+                res.push(s);
+                res.push(Doc::text(" "));
             }
             CodeBlock | ContentBlock => {
-                ctx.push_raw_in(" ", &mut res);
-                ctx.push_raw_in(s, &mut res);
+                res.push(Doc::text(" "));
+                res.push(s);
             }
             Else => {
-                ctx.push_raw_in(" ", &mut res);
-                ctx.push_raw_in(s, &mut res);
+                res.push(Doc::text(" "));
+                res.push(s);
                 if node.next_sibling_kind() == Some(Conditional) {
-                    ctx.push_raw_in(" ", &mut res);
+                    res.push(Doc::text(" "));
                 }
             }
-            _ => ctx.push_raw_in(s, &mut res),
+            _ => res.push(s),
         }
     }
-    res
+    ctx.lost_context();
+    Doc::Concat(res)
 }
 
-#[instrument(skip_all, ret)]
-pub(crate) fn format_named_args(parent: &LinkedNode, children: &[String], ctx: &mut Ctx) -> String {
-    let mut res = String::new();
-    for (s, node) in children.iter().zip(parent.children()) {
+#[instrument(skip_all)]
+pub(crate) fn format_named_args(parent: &LinkedNode, children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    let mut res = vec![];
+    for (s, node) in children.into_iter().zip(parent.children()) {
         match node.kind() {
-            _ if ctx.off => res.push_str(node.text()),
+            _ if ctx.off => res.push(deep_no_format(&node)),
             Show | Set => {
-                ctx.push_raw_in(s, &mut res);
-                ctx.push_in(" ", &mut res);
+                res.push(s);
+                res.push(Doc::text(" "));
             }
-            Colon => res.push_str(": "),
+            Colon => res.push(Doc::text(": ")),
             Space => {}
-            LineComment | BlockComment => ctx.push_raw_in(s, &mut res),
-            _ => {
-                ctx.push_raw_in(s, &mut res);
-            }
+            _ => res.push(s),
         }
     }
-    res
+    ctx.lost_context();
+    Doc::Concat(res)
 }
 
-#[instrument(skip_all, ret)]
-pub(crate) fn format_let_binding(
-    parent: &LinkedNode,
-    children: &[String],
-    ctx: &mut Ctx,
-) -> String {
-    let mut res = String::new();
-    for (s, node) in children.iter().zip(parent.children()) {
+#[instrument(skip_all)]
+pub(crate) fn format_let_binding(parent: &LinkedNode, children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    let mut res = vec![];
+    for (s, node) in children.into_iter().zip(parent.children()) {
         match node.kind() {
-            _ if ctx.off => res.push_str(node.text()),
+            _ if ctx.off => res.push(deep_no_format(&node)),
             Eq => {
-                ctx.push_in(" ", &mut res);
-                ctx.push_in(s, &mut res);
-                ctx.push_in(" ", &mut res);
-            }
-            Space => ctx.push_in(s, &mut res),
-            _ => {
-                ctx.push_raw_in(s, &mut res);
+                res.push(Doc::text(" "));
+                res.push(s);
+                res.push(Doc::text(" "));
             }
+            // the spaces around `=` are already taken care of.
+            Space if utils::next_is_ignoring(&node, Eq, &[]) => {}
+            Space if utils::prev_is_ignoring(&node, Eq, &[]) => {}
+            Space => res.push(s),
+            _ => res.push(s),
         }
     }
-    res
+    ctx.lost_context();
+    Doc::Concat(res)
 }
 
-fn format_comment_handling_disable(parent: &LinkedNode, _: &[String], ctx: &mut Ctx) -> String {
+fn format_comment_handling_disable(parent: &LinkedNode, _: &[Doc], ctx: &mut Ctx) -> Doc {
     ctx.lost_context();
     if parent.text().contains("typstfmt::off") {
         ctx.off = true;
@@ -196,23 +262,22 @@ fn format_comment_handling_disable(parent: &LinkedNode, _: &[String], ctx: &mut
     } else if parent.text().contains("typstfmt::") {
         warn!("your comment contains `typstfmt::` not followed by `on` or `off`, did you make a typo?");
     }
-    parent.text().to_string()
+    Doc::verbatim(parent.text().as_str())
 }
 
-fn format_list_enum(parent: &LinkedNode, children: &[String], ctx: &mut Ctx) -> String {
-    let mut res = String::new();
-    for (s, node) in children.iter().zip(parent.children()) {
+fn format_list_enum(parent: &LinkedNode, children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    let mut res = vec![];
+    let mut inner = vec![];
+    for (s, node) in children.into_iter().zip(parent.children()) {
         match node.kind() {
-            _ if ctx.off => res.push_str(node.text()),
-            EnumMarker | ListMarker | TermMarker => {
-                ctx.push_raw_in(node.text(), &mut res);
-            }
-            _ => {
-                ctx.push_raw_indent(s, &mut res);
-            }
+            _ if ctx.off => inner.push(deep_no_format(&node)),
+            EnumMarker | ListMarker | TermMarker => res.push(Doc::text(node.text().as_str())),
+            _ => inner.push(s),
         }
     }
-    res
+    res.push(Doc::indent(Doc::Concat(inner)));
+    ctx.lost_context();
+    Doc::Concat(res)
 }
 
 #[cfg(test)]
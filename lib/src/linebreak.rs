@@ -0,0 +1,40 @@
+//! The kinsoku rules for the spaces of chinese and japanese text.
+//!
+//! This isn't line breaking between CJK characters: typst 0.7 renders a
+//! newline between two of them as a space, so breaking a paragraph without
+//! spaces would add spaces to the document. The lines are only broken at the
+//! spaces already there, and not at the ones where a line would start with
+//! closing punctuation or small kana, or end with an opening bracket.
+
+/// the full-width opening brackets, a line can't end with these.
+const OPENING: &str = "「『（【〈《〔［｛〘〖｟";
+
+/// the full-width closing brackets and punctuation, iteration marks, the
+/// prolonged sound mark and the small kana, a line can't start with these.
+const CANNOT_START_LINE: &str =
+    "」』）】〉》〕］｝〙〗｠、。，．！？：；・…‥〜～ー々〻ゝゞヽヾ゛゜\
+    ぁぃぅぇぉっゃゅょゎゕゖァィゥェォッャュョヮヵヶㇰㇱㇲㇳㇴㇵㇶㇷㇸㇹㇺㇻㇼㇽㇾㇿ";
+
+/// true if a line can break at a space between the two characters.
+pub(crate) fn can_break_between(before: char, after: char) -> bool {
+    !OPENING.contains(before) && !CANNOT_START_LINE.contains(after)
+}
+
+/// true if the character takes two columns, like the ideographs, kana, hangul
+/// and full-width forms (the W and F classes of UAX #11).
+pub(crate) fn is_wide(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{115F}' // hangul jamo
+        | '\u{2E80}'..='\u{303E}' // radicals, CJK symbols and punctuation
+        | '\u{3041}'..='\u{33FF}' // kana, bopomofo, enclosed and compatibility
+        | '\u{3400}'..='\u{4DBF}' // extension A
+        | '\u{4E00}'..='\u{9FFF}' // unified ideographs
+        | '\u{A000}'..='\u{A4CF}' // yi
+        | '\u{AC00}'..='\u{D7A3}' // hangul syllables
+        | '\u{F900}'..='\u{FAFF}' // compatibility ideographs
+        | '\u{FE30}'..='\u{FE4F}' // compatibility forms
+        | '\u{FF00}'..='\u{FF60}' // full-width forms
+        | '\u{FFE0}'..='\u{FFE6}'
+        | '\u{20000}'..='\u{3FFFD}' // extensions B and after
+    )
+}
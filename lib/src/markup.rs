@@ -2,54 +2,80 @@ use super::*;
 use typst_syntax::ast::AstNode;
 
 #[instrument(skip_all)]
-pub(crate) fn format_content_blocks(
-    parent: &LinkedNode,
-    children: &[String],
-    ctx: &mut Ctx,
-) -> String {
-    let mut res = String::new();
+pub(crate) fn format_content_blocks(parent: &LinkedNode, children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    let mut res = vec![];
+    let mut inner = vec![];
     let markup = parent
         .cast_first_match::<typst_syntax::ast::Markup>()
         .unwrap_or_default();
     let first_space = markup.as_untyped().children().next();
+    let last_space = markup.as_untyped().children().last();
     let spaced = first_space.is_some_and(|x| x.kind() == Space);
 
-    for (s, node) in children.iter().zip(parent.children()) {
+    for (s, node) in children.into_iter().zip(parent.children()) {
         match node.kind() {
-            _ if ctx.off => res.push_str(node.text()),
+            _ if ctx.off => inner.push(deep_no_format(&node)),
             LineComment | BlockComment => {
                 let buf = format_comment_handling_disable(&node, &[], ctx);
-                ctx.push_raw_in(&buf, &mut res);
+                inner.push(buf);
             }
-            RightBracket if spaced => {
-                let space_type = if first_space.unwrap().text().contains('\n') {
-                    '\n'
-                } else {
-                    ' '
-                };
-                if !res.ends_with(space_type) {
-                    while res.ends_with('\n') || res.ends_with(' ') {
-                        res.replace_range(res.len() - 1..res.len(), "");
+            LeftBracket => res.push(s),
+            RightBracket => {
+                let mut trailing = String::new();
+                if spaced {
+                    let space_type = if first_space.unwrap().text().contains('\n') {
+                        '\n'
+                    } else {
+                        ' '
+                    };
+                    trailing.push(space_type);
+                    // keep the blank line if there was one
+                    if let Some(last) = last_space.filter(|x| [Space, Parbreak].contains(&x.kind()))
+                    {
+                        let newlines = last.text().matches('\n').count();
+                        if space_type == '\n' && newlines >= 2 {
+                            trailing.push('\n');
+                        }
                     }
-                    res.push(space_type);
+                    doc::trim_end(&mut inner);
                 }
-                ctx.push_raw_in(s, &mut res)
+                res.push(Doc::indent(Doc::Concat(std::mem::take(&mut inner))));
+                res.push(Doc::Text(trailing));
+                res.push(s);
             }
-            _ => ctx.push_raw_indent(s, &mut res),
+            _ => inner.push(s),
         }
     }
-    res
+    // the closing bracket was reached while disabled, or is missing.
+    if !inner.is_empty() {
+        res.push(Doc::indent(Doc::Concat(inner)));
+    }
+    ctx.lost_context();
+    Doc::Concat(res)
 }
 
 // break lines so they won't go over max_line_length
 #[instrument(skip_all)]
-pub(crate) fn format_markup(parent: &LinkedNode, children: &[String], ctx: &mut Ctx) -> String {
-    let mut res = String::new();
+pub(crate) fn format_markup(parent: &LinkedNode, children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    format_markup_slice(parent, 0, children, ctx)
+}
+
+/// formats only the children of the markup starting at index `first`, one for
+/// each of the given docs.
+pub(crate) fn format_markup_slice(
+    parent: &LinkedNode,
+    first: usize,
+    children: Vec<Doc>,
+    ctx: &mut Ctx,
+) -> Doc {
+    let mut res = vec![];
     let mut skip_until = None;
+    let end = first + children.len();
+    let mut children = children.into_iter().map(Some).collect_vec();
 
-    for (idx, (s, node)) in children.iter().zip(parent.children()).enumerate() {
+    for (idx, node) in parent.children().enumerate().take(end).skip(first) {
         match node.kind() {
-            _ if ctx.off => res.push_str(&deep_no_format(&node)), // todo, interaction with line below?
+            _ if ctx.off => res.push(deep_no_format(&node)), // todo, interaction with line below?
             _ if skip_until.is_some_and(|skip| idx <= skip) => {}
             LineComment | BlockComment => {
                 let buf = format_comment_handling_disable(&node, &[], ctx);
@@ -61,24 +87,30 @@ pub(crate) fn format_markup(parent: &LinkedNode, children: &[String], ctx: &mut
                     let s = utils::get_prev_ignoring(&node, &[])
                         .map(|x| x.text().to_string())
                         .unwrap_or_default();
-                    let s = s.split('\n').last().unwrap_or_default();
-                    ctx.push_raw_in(s, &mut res);
+                    let s = s.rsplit('\n').next().unwrap_or_default();
+                    res.push(Doc::verbatim(s));
                 }
-                ctx.push_raw_in(&buf, &mut res);
+                res.push(buf);
             }
-            Space => {
-                // careful, s has already been formatted.
-                ctx.push_raw_in(s, &mut res);
+            Space
+                if ctx.config.line_wrap == LineWrap::Unwrap
+                    && node.text().contains('\n')
+                    && between_inline_markup(&node) =>
+            {
+                res.push(Doc::text(" "))
             }
-            Text if !ctx.config.line_wrap => ctx.push_raw_in(s, &mut res),
-            Text => {
+            Text if ctx.config.line_wrap != LineWrap::Off => {
                 // We eat all the following nodes if they're in `[Space, Text, Emph, Strong, Label, Ref]`
-                // then we format ourselves breaking or spacing.
+                // then we let the printer fill the lines with the words.
                 skip_until = Some(idx);
                 let mut this = node;
-                let mut add = s.to_string();
+                let mut words = vec![vec![]];
+                // the words following a newline.
+                let mut line_starts = vec![];
+                push_in_words(&mut words, children[idx - first].take().unwrap());
                 loop {
-                    let next = utils::find_next(&this, &|_| true);
+                    let next = utils::find_next(&this, &|_| true)
+                        .filter(|_| skip_until.is_some_and(|skip| skip + 1 < end));
                     match next.as_ref() {
                         Some(next) => {
                             if ![
@@ -113,38 +145,137 @@ pub(crate) fn format_markup(parent: &LinkedNode, children: &[String], ctx: &mut
 
                     *skip_until.as_mut().unwrap() += 1;
                     this = next.unwrap();
+                    let s = children[skip_until.unwrap() - first].take().unwrap();
                     match this {
-                        ref x if x.kind() == Space => add.push(' '),
-                        _ => add.push_str(&children[skip_until.unwrap()]),
+                        ref x if x.kind() == Space => {
+                            words.push(vec![]);
+                            if x.text().contains('\n') {
+                                line_starts.push(words.len() - 1);
+                            }
+                        }
+                        _ => push_in_words(&mut words, s),
+                    }
+                }
+                let in_heading = parent.parent_kind() == Some(Heading);
+                let mut after_newline = false;
+                let mut kept = vec![];
+                for (idx, word) in words.into_iter().enumerate() {
+                    after_newline |= line_starts.contains(&idx);
+                    if !word.is_empty() || (parent.parent_kind() == Some(ContentBlock)) {
+                        kept.push((std::mem::take(&mut after_newline), word));
                     }
                 }
-                let add = add
-                    .split(' ')
-                    .filter(|&x| !x.is_empty() || (parent.parent_kind() == Some(ContentBlock)))
-                    .collect_vec();
-                for (j, word) in add.iter().enumerate() {
-                    ctx.push_raw_in(word, &mut res);
-                    if let Some(next_word) = add.get(j + 1) {
-                        if utils::first_line_length(next_word)
-                        + 1 // the space we're adding
-                        + utils::last_line_length(&res)
-                            <= ctx.config.max_line_length
-                            || (parent.parent_kind() == Some(Heading))
+                let (after_newline, words): (Vec<_>, Vec<_>) = kept.into_iter().unzip();
+                let mut fill = vec![];
+                for (idx, word) in words.iter().enumerate() {
+                    if idx != 0 {
+                        let hard_line = match ctx.config.line_wrap {
+                            LineWrap::Preserve => after_newline[idx],
+                            LineWrap::Sentence => starts_a_sentence(&words[idx - 1], word),
+                            _ => false,
+                        };
+                        let separator = if in_heading
+                            || starts_a_line_construct(word)
+                            || ctx.config.line_wrap == LineWrap::Unwrap
                         {
-                            ctx.push_raw_in(" ", &mut res);
+                            // breaking before it would change the meaning of the text.
+                            Doc::text(" ")
+                        } else if hard_line {
+                            Doc::HardLine
+                        } else if cannot_break_between(&words[idx - 1], word) {
+                            Doc::text(" ")
                         } else {
-                            ctx.push_raw_in("\n", &mut res);
-                        }
+                            Doc::Line
+                        };
+                        fill.push(separator);
                     }
-                    // ctx.push_raw_in("SsS", &mut res);
+                    fill.push(Doc::Concat(word.clone()));
                 }
+                res.push(Doc::Fill(fill));
             }
-            _ => {
-                ctx.push_raw_in(s, &mut res);
-            }
+            _ => res.push(children[idx - first].take().unwrap()),
         }
     }
 
     ctx.lost_context();
-    res
+    Doc::Concat(res)
+}
+
+/// true if the word at the start of a line would be parsed as the marker of a
+/// list (`-`), an enum (`+` or `12.`), a term (`/`) or a heading (`=`, `==`...).
+fn starts_a_line_construct(word: &[Doc]) -> bool {
+    let [Doc::Text(word)] = word else {
+        return false;
+    };
+    let is_enum_number = |s: &str| {
+        s.strip_suffix('.')
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+    };
+    ["-", "+", "/"].contains(&word.as_str())
+        || (!word.is_empty() && word.chars().all(|c| c == '='))
+        || is_enum_number(word)
+}
+
+/// true if the nodes around the space are inline markup, so it doesn't matter
+/// if it's a newline, unlike after a comment or a statement, before a list item.
+fn between_inline_markup(space: &LinkedNode) -> bool {
+    let inline = |node: Option<LinkedNode>| {
+        node.is_some_and(|node| {
+            [
+                Text, Emph, Strong, Raw, Escape, Shorthand, SmartQuote, Link, Label, Ref, Equation,
+            ]
+            .contains(&node.kind())
+        })
+    };
+    inline(utils::prev_sibling_or_trivia(space)) && inline(utils::next_sibling_or_trivia(space))
+}
+
+/// true if the previous word ends a sentence, with `.`, `!` or `?` maybe
+/// followed by closing quotes, parenthesis or emphasis, and the word starts with
+/// a capital letter, so `e.g. this` isn't two sentences.
+fn starts_a_sentence(previous: &[Doc], word: &[Doc]) -> bool {
+    let ends_sentence = text_of(previous)
+        .trim_end_matches(['"', '\'', ')', ']', '’', '”', '*', '_'])
+        .ends_with(['.', '!', '?']);
+    let capital = text_of(word)
+        .trim_start_matches(['"', '\'', '(', '[', '‘', '“', '*', '_'])
+        .starts_with(char::is_uppercase);
+    ends_sentence && capital
+}
+
+/// true if a line can't break between the words, see [linebreak].
+fn cannot_break_between(previous: &[Doc], word: &[Doc]) -> bool {
+    let before = text_of(previous).chars().last();
+    let after = text_of(word).chars().next();
+    before
+        .zip(after)
+        .is_some_and(|(before, after)| !linebreak::can_break_between(before, after))
+}
+
+/// the text of the docs, without their breaks.
+fn text_of(docs: &[Doc]) -> String {
+    docs.iter()
+        .map(|doc| match doc {
+            Doc::Text(s) | Doc::Verbatim(s) => s.clone(),
+            Doc::Concat(docs) => text_of(docs),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+/// splits the text on spaces, other docs are glued to the last word.
+fn push_in_words(words: &mut Vec<Vec<Doc>>, doc: Doc) {
+    match doc {
+        Doc::Text(text) => {
+            for (idx, word) in text.split(' ').enumerate() {
+                if idx != 0 {
+                    words.push(vec![]);
+                }
+                if !word.is_empty() {
+                    words.last_mut().unwrap().push(Doc::text(word));
+                }
+            }
+        }
+        doc => words.last_mut().unwrap().push(doc),
+    }
 }
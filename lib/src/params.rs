@@ -4,25 +4,12 @@ use super::*;
 use crate::utils::{get_next_ignoring, next_is_ignoring, Btype};
 
 #[instrument(skip_all)]
-/// format args using [format_args_tight] or [format_args_breaking] depending on the context.
+/// format args as a group that the printer breaks if it doesn't fit on the line.
 /// - if number of args is 0, format tight.
-/// - if line gets above max_length - 7 in tight mode, format breaking. (see TODO: why plus 7)
-pub(crate) fn format_args(parent: &LinkedNode, children: &[String], ctx: &mut Ctx) -> String {
-    // check if any children is markup and contains a linebreak, if so, breaking
-    // let mut res = vec![];
-    // utils::find_children(&mut res, parent, &|c| {
-    //     c.parent_kind() == Some(Markup)
-    //         && (c.kind() == Parbreak || (c.kind() == Space) && c.text().contains('\n'))
-    // });
-
-    // if !res.is_empty() {
-    //     return format_args_breaking(parent, children, ctx);
-    // }
-
-    if parent.children().any(|c| c.kind() == LineComment) {
-        return format_args_breaking(parent, children, ctx);
-    }
-
+/// - if there is a line comment, always break.
+///
+/// when broken, each arg is on its own line and a trailing comma is added.
+pub(crate) fn format_args(parent: &LinkedNode, children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
     let number_of_args = parent
         .children()
         .filter_map(|node| {
@@ -42,67 +29,26 @@ pub(crate) fn format_args(parent: &LinkedNode, children: &[String], ctx: &mut Ct
             }
         })
         .count();
+    let should_break = parent.children().any(|c| c.kind() == LineComment);
 
-    if number_of_args == 0 {
+    if number_of_args == 0 && !should_break {
         return format_args_tight(parent, children, ctx);
     }
-
-    let res = format_args_tight(parent, children, ctx);
-    // TODO: why plus 7
-    // why plus 7? if you remove it you'll notice the official example
-    // fails, since the inner line is broken before reaching the limit,
-    // it's difficult to have a condition like "if one of my child had
-    // to break in order to not go over the max_len, break" So I had to
-    // resort to this hack. A more meaningful approach is desired.
-    if utils::max_line_length(&res) + 7 >= ctx.config.max_line_length {
-        return format_args_breaking(parent, children, ctx);
-    }
-    res
+    format_args_group(parent, children, ctx, should_break)
 }
 
-pub(crate) fn format_args_tight(
-    parent: &LinkedNode<'_>,
-    children: &[String],
-    ctx: &mut Ctx,
-) -> String {
-    let mut res = String::new();
-    let is_destruct_and_one_arg = typst_syntax::ast::Destructuring::from_untyped(parent)
-        .is_some_and(|x| x.bindings().count() == 1);
-    let mut missing_trailing = is_destruct_and_one_arg;
-
-    for (s, node) in children.iter().zip(parent.children()) {
-        let is_last =
-            utils::next_is_ignoring(&node, RightParen, &[Space, LineComment, BlockComment]);
-
+/// without any possible break, used when there are no args.
+pub(crate) fn format_args_tight(parent: &LinkedNode<'_>, children: Vec<Doc>, ctx: &mut Ctx) -> Doc {
+    let mut res = vec![];
+    for (s, node) in children.into_iter().zip(parent.children()) {
         match node.kind() {
-            _ if ctx.off => res.push_str(node.text()),
+            _ if ctx.off => res.push(deep_no_format(&node)),
             Space => {}
-            Comma => {
-                let is_last_comma = utils::find_next(&node, &|x| x.kind() == Comma).is_none();
-                let is_trailing =
-                    utils::next_is_ignoring(&node, RightParen, &[Space, BlockComment]);
-
-                missing_trailing = is_last_comma && !is_trailing;
-                if utils::next_is_ignoring(&node, RightParen, &[Space]) {
-                    // not putting the comma in would result in a parenthesized expression, not an array
-                    // "(a,) != (a)"
-                    if parent.kind() == Array || is_destruct_and_one_arg {
-                        ctx.push_raw_in(",", &mut res);
-                    }
-                } else {
-                    ctx.push_raw_in(s, &mut res);
-                    ctx.push_in(" ", &mut res);
-                }
-            }
-            _ => {
-                ctx.push_raw_in(s, &mut res);
-                if is_last && missing_trailing && is_destruct_and_one_arg {
-                    ctx.push_raw_in(",", &mut res);
-                }
-            }
+            _ => res.push(s),
         }
     }
-    res
+    ctx.lost_context();
+    Doc::Concat(res)
 }
 
 #[derive(Debug, Default)]
@@ -117,74 +63,74 @@ impl TrailingBlockDetect {
     }
 }
 
-pub(crate) fn format_args_breaking(
+pub(crate) fn format_args_group(
     parent: &LinkedNode<'_>,
-    children: &[String],
+    children: Vec<Doc>,
     ctx: &mut Ctx,
-) -> String {
-    let mut res = String::new();
+    should_break: bool,
+) -> Doc {
+    let mut res = vec![];
+    let mut group = vec![];
+    let mut body = vec![];
     let mut is_trailing_block = TrailingBlockDetect::default();
     let is_block_math = utils::block_type(parent) == Btype::Math;
     let is_parenthesized = parent.kind() == Parenthesized;
+    let is_destruct_and_one_arg = typst_syntax::ast::Destructuring::from_untyped(parent)
+        .is_some_and(|x| x.bindings().count() == 1);
     let mut missing_trailing_comma = !(is_parenthesized || is_block_math);
     // only used with experimental flag in config for now
     let mut consecutive_items = 0;
+    let mut line_width = Some(0);
 
-    for (s, node) in children.iter().zip(parent.children()) {
+    for (s, node) in children.into_iter().zip(parent.children()) {
         let is_last =
             utils::next_is_ignoring(&node, RightParen, &[Space, LineComment, BlockComment]);
         match node.kind() {
-            _ if ctx.off => res.push_str(node.text()),
+            _ if ctx.off => body.push(deep_no_format(&node)),
             LeftParen => {
                 is_trailing_block.left_par = true;
-                ctx.push_raw_in(s, &mut res);
-                ctx.push_raw_in("\n", &mut res);
-                ctx.push_raw_in(&ctx.get_indent(), &mut res);
+                group.push(s);
+                body.push(Doc::SoftLine);
             }
             RightParen => {
                 is_trailing_block.right_par = true;
-                if is_parenthesized || is_block_math {
-                    // no trailing comma we don't have a newline!
-                    ctx.push_in("\n", &mut res);
+                let body = std::mem::take(&mut body);
+                if !body.iter().all(|d| matches!(d, Doc::SoftLine)) {
+                    group.push(Doc::indent_if_break(Doc::Concat(body)));
+                    group.push(Doc::SoftLine);
                 }
-                ctx.push_raw_in(s, &mut res);
+                group.push(s);
             }
             LineComment | BlockComment => {
                 consecutive_items = 0;
+                line_width = Some(0);
                 if utils::prev_is_ignoring(&node, LineComment, &[Space])
                     || utils::prev_is_ignoring(&node, BlockComment, &[Space])
                 {
-                    ctx.push_raw_in(s, &mut res);
-                    ctx.push_in("\n", &mut res);
+                    body.push(s);
                 } else {
                     let prev = node.prev_sibling().unwrap();
-                    let mark = res.rfind(|x| x != ' ' && x != '\n').unwrap() + 1;
                     let prev_maybe_space = get_next_ignoring(&prev, &[]);
-                    res = res[..mark].to_string();
+                    let at_start = prev.kind() == LeftParen;
+                    doc::trim_end(&mut body);
 
                     match prev_maybe_space {
                         Some(space) if space.kind() == Space && space.text().contains('\n') => {
-                            ctx.push_raw_in("\n", &mut res);
-                            ctx.push_raw_in(&ctx.get_indent(), &mut res);
-                            ctx.push_raw_in(s, &mut res);
-                        }
-                        _ => {
-                            ctx.push_raw_in(" ", &mut res);
-                            ctx.push_raw_in(s, &mut res);
+                            body.push(if at_start { Doc::SoftLine } else { Doc::Line });
                         }
+                        // like the SoftLine when the group is flat.
+                        _ if at_start => {}
+                        _ => body.push(Doc::text(" ")),
                     }
-                    ctx.push_raw_in("\n", &mut res);
-                    ctx.consec_new_line = 2;
+                    body.push(s);
                 }
 
                 if !next_is_ignoring(&node, RightParen, &[Space]) {
-                    ctx.push_raw_in(&ctx.get_indent(), &mut res);
-                    ctx.just_spaced = true;
+                    body.push(Doc::Line);
                 }
             }
             Space => {}
             // handles trailing comma
-            // handles Line comment
             Comma => {
                 let is_last_comma = utils::find_next(&node, &|x| x.kind() == Comma).is_none();
                 let is_trailing =
@@ -192,36 +138,53 @@ pub(crate) fn format_args_breaking(
                 missing_trailing_comma = is_last_comma && !is_trailing;
 
                 if is_last_comma && is_trailing {
-                    // no indent
-                    ctx.push_raw_in(s, &mut res);
-                    ctx.push_raw_in("\n", &mut res);
+                    // not putting the comma in would result in a parenthesized expression, not an array
+                    // "(a,) != (a)"
+                    if parent.kind() == Array || is_destruct_and_one_arg {
+                        body.push(s);
+                    } else {
+                        body.push(Doc::if_break(s, Doc::nil()));
+                    }
                 } else if !(ctx.config.experimental_args_breaking_consecutive || is_block_math)
                     || consecutive_items >= 3
-                    || s.contains('\n')
-                    || res
-                        .lines()
-                        .last()
-                        .is_some_and(|line| utils::max_line_length(&format!("{line}, ")) >= 10)
+                    || !matches!(line_width, Some(width) if width + ", ".len() < 10)
                 {
-                    ctx.push_raw_in(s, &mut res);
-                    ctx.push_raw_in("\n", &mut res);
-                    ctx.push_raw_in(&ctx.get_indent(), &mut res);
-
+                    body.push(s);
+                    body.push(Doc::Line);
                     consecutive_items = 0;
+                    line_width = Some(0);
                 } else {
                     consecutive_items += 1;
-                    ctx.push_raw_in(s, &mut res);
-                    ctx.push_raw_in(" ", &mut res);
+                    body.push(s);
+                    body.push(Doc::text(" "));
+                    line_width = line_width.map(|width| width + ", ".len());
+                }
+            }
+            ContentBlock if is_trailing_block.is_trailing_block() => {
+                if is_trailing_block.left_par {
+                    res.push(s);
+                } else {
+                    group.push(s);
                 }
             }
-            ContentBlock if is_trailing_block.is_trailing_block() => ctx.push_raw_in(s, &mut res),
             _ => {
-                ctx.push_raw_indent(s, &mut res);
+                line_width = line_width.zip(s.flat_width()).map(|(a, b)| a + b);
+                body.push(s);
                 if is_last && missing_trailing_comma {
-                    ctx.push_raw_in(",\n", &mut res);
+                    if is_destruct_and_one_arg {
+                        body.push(Doc::text(","));
+                    } else {
+                        body.push(Doc::if_break(Doc::text(","), Doc::nil()));
+                    }
                 }
             }
         }
     }
-    res
+    // the closing paren was reached while disabled, or is missing.
+    if !body.is_empty() {
+        group.push(Doc::Concat(body));
+    }
+    ctx.lost_context();
+    res.insert(0, Doc::group_breaking(Doc::Concat(group), should_break));
+    Doc::Concat(res)
 }
@@ -0,0 +1,155 @@
+use std::ops::{Range, RangeInclusive};
+
+use super::*;
+
+/// Formats only the smallest syntax nodes covering `range`, byte offsets in `s`,
+/// the rest of the source is left untouched.
+///
+/// Returns the range that was actually replaced, which contains the given one,
+/// with the text to put in its place.
+///
+/// Formatting a part of markup always includes the whole paragraphs it touches,
+/// so the lines are wrapped the same way formatting the whole file would.
+#[must_use]
+pub fn format_range(s: &str, range: Range<usize>, config: Config) -> (Range<usize>, String) {
+    let start = range.start.min(s.len());
+    let range = start..range.end.clamp(start, s.len());
+
+    // the offsets in the source with its tabs expanded.
+    let (expanded, tabs) = expand_tabs(s, config.indent_space);
+    let s = expanded.as_str();
+    let shift = config.indent_space as isize - 1;
+    let to_expanded = |offset: usize| {
+        let before = tabs.iter().take_while(|tab| **tab < offset).count();
+        offset.saturating_add_signed(before as isize * shift)
+    };
+    let range = to_expanded(range.start)..to_expanded(range.end);
+
+    let init = parse(s);
+    let root = LinkedNode::new(&init);
+    let node = covering_node(&root, &range);
+    let mut context = Ctx::from_config(config);
+
+    let (replaced, doc) = match selected_children(&node, &range) {
+        Some(selected) => {
+            let nodes = node.children().collect_vec();
+            let replaced = nodes[*selected.start()].offset()..nodes[*selected.end()].range().end;
+            context.off = is_off_at(&root, replaced.start);
+            let children = nodes[selected.clone()]
+                .iter()
+                .map(|child| visit(child, &mut context))
+                .collect_vec();
+            let doc = if node.kind() == Markup {
+                markup::format_markup_slice(&node, *selected.start(), children, &mut context)
+            } else {
+                Doc::Concat(children)
+            };
+            (replaced, doc)
+        }
+        None => {
+            context.off = is_off_at(&root, node.offset());
+            (node.range(), visit(&node, &mut context))
+        }
+    };
+
+    let line_start = s[..replaced.start].rfind('\n').map_or(0, |i| i + 1);
+    let col = doc::width(&s[line_start..replaced.start]);
+    let base_indent = s[line_start..replaced.start]
+        .chars()
+        .take_while(|c| [' ', '\t'].contains(c))
+        .map(|c| {
+            if c == '\t' {
+                context.config.indent_space
+            } else {
+                1
+            }
+        })
+        .sum();
+
+    let res = doc::print_at(&doc, &context.config, col, base_indent);
+    let res = res
+        .replace("\r\n", "\n")
+        .replace('\n', context.config.newline_style.newline(s));
+    // the nodes start and end at the same place in both sources.
+    let to_original = |offset: usize| {
+        let before = tabs
+            .iter()
+            .enumerate()
+            .take_while(|(idx, tab)| (**tab as isize + *idx as isize * shift) < offset as isize)
+            .count();
+        offset.saturating_add_signed(-(before as isize) * shift)
+    };
+    let replaced = to_original(replaced.start)..to_original(replaced.end);
+    (replaced, res)
+}
+
+/// the deepest node that isn't a token and contains the whole range.
+fn covering_node<'a>(node: &LinkedNode<'a>, range: &Range<usize>) -> LinkedNode<'a> {
+    let mut node = node.clone();
+    while let Some(child) = node.children().find(|child| {
+        child.children().len() > 0
+            && child.offset() <= range.start
+            && range.end <= child.range().end
+    }) {
+        node = child;
+    }
+    node
+}
+
+/// the indices of the children of a markup or code node touched by the range,
+/// `None` if the whole node should be formatted.
+///
+/// For markup the selection grows to the paragraphs boundaries, spaces at
+/// both ends are left out when they're not in the range.
+fn selected_children(node: &LinkedNode, range: &Range<usize>) -> Option<RangeInclusive<usize>> {
+    if ![Markup, Code].contains(&node.kind()) {
+        return None;
+    }
+    let nodes = node.children().collect_vec();
+    // an empty range still selects the node it's in.
+    let end = range.end.max(range.start + 1);
+    let mut first = nodes
+        .iter()
+        .position(|c| c.offset() < end && range.start < c.range().end)?;
+    let mut last = nodes
+        .iter()
+        .rposition(|c| c.offset() < end && range.start < c.range().end)?;
+
+    if node.kind() == Markup {
+        while first > 0 && nodes[first - 1].kind() != Parbreak {
+            first -= 1;
+        }
+        while last + 1 < nodes.len() && nodes[last + 1].kind() != Parbreak {
+            last += 1;
+        }
+    }
+    let is_space = |node: &LinkedNode| [Space, Parbreak].contains(&node.kind());
+    while first < last && is_space(&nodes[first]) && nodes[first + 1].offset() <= range.start {
+        first += 1;
+    }
+    while first < last && is_space(&nodes[last]) && range.end <= nodes[last - 1].range().end {
+        last -= 1;
+    }
+
+    if first == 0 && last + 1 == nodes.len() {
+        return None;
+    }
+    Some(first..=last)
+}
+
+/// true if a `typstfmt::off` comment before the offset wasn't followed by a
+/// `typstfmt::on` one.
+fn is_off_at(root: &LinkedNode, offset: usize) -> bool {
+    fn walk(node: &LinkedNode, offset: usize, off: &mut bool) {
+        for child in node.children().take_while(|c| c.offset() < offset) {
+            match child.kind() {
+                LineComment | BlockComment if child.text().contains("typstfmt::off") => *off = true,
+                LineComment | BlockComment if child.text().contains("typstfmt::on") => *off = false,
+                _ => walk(&child, offset, off),
+            }
+        }
+    }
+    let mut off = false;
+    walk(root, offset, &mut off);
+    off
+}
@@ -10,6 +10,20 @@ make_test!(block_comment_nested, BLOCK_COMMENT_NESTED);
 make_test!(code_comment, CODE_COMMENT);
 make_test!(end_comments, END_COMMENTS);
 make_test!(start_with_comment, START_WITH_COMMENT);
+make_test!(multiline_block_comment, MULTILINE_BLOCK_COMMENT);
+make_test!(
+    multiline_block_comment_in_args,
+    "#{\n  f(\n    /* a\n  b */ x,\n  )\n}"
+);
+make_test!(block_comment_only, "#{\n  /* a\n  b */\n}");
+test_eq!(
+    comment_trailing_spaces,
+    "#let a = 1 // a comment  \n/* b  \nc */ d"
+);
+test_eq!(
+    comment_trailing_spaces_in_code,
+    "#{\n  let a = 1 // a comment \n  /* b  \n  c */\n}"
+);
 make_test!(
     args_comment_end,
     "#func(
@@ -74,3 +88,14 @@ const START_WITH_COMMENT: &str = r#"#table(
   fill: value,
   [*Pros*],)
 "#;
+
+const MULTILINE_BLOCK_COMMENT: &str = r#"- item
+  #{
+    /* the comment
+       keeps its lines */
+    let a = (
+      x: 1, /* and
+      here */
+      y: 2,
+    )
+  }"#;
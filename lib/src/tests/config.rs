@@ -0,0 +1,140 @@
+use std::path::Path;
+
+use super::*;
+
+#[test]
+fn includes_everything_by_default() {
+    assert_eq!(Config::default().includes(Path::new("a/b.typ")), Ok(true));
+}
+
+#[test]
+fn exclude_matches_the_end_of_the_path() {
+    let config = Config {
+        exclude: vec!["vendor/**".into(), "*.gen.typ".into()],
+        ..Default::default()
+    };
+    assert_eq!(config.includes(Path::new("./vendor/a.typ")), Ok(false));
+    assert_eq!(config.includes(Path::new("a/vendor/b/c.typ")), Ok(false));
+    assert_eq!(config.includes(Path::new("a/b.gen.typ")), Ok(false));
+    assert_eq!(config.includes(Path::new("a/b.typ")), Ok(true));
+}
+
+#[test]
+fn exclude_wins_over_include() {
+    let config = Config {
+        include: vec!["chapters/**".into()],
+        exclude: vec!["chapters/draft.typ".into()],
+        ..Default::default()
+    };
+    assert_eq!(config.includes(Path::new("chapters/one.typ")), Ok(true));
+    assert_eq!(config.includes(Path::new("chapters/draft.typ")), Ok(false));
+    assert_eq!(config.includes(Path::new("main.typ")), Ok(false));
+}
+
+#[test]
+fn from_toml() {
+    let config = Config::from_toml("exclude = [\"vendor/**\"]").unwrap();
+    assert_eq!(config.exclude, ["vendor/**"]);
+    assert_eq!(config.max_line_length, Config::default().max_line_length);
+}
+
+#[test]
+fn layers_override_the_keys_they_set() {
+    let (config, origins) = Config::from_toml_layers(&[
+        (
+            "global".into(),
+            "max_line_length = 100\nindent_space = 4".into(),
+        ),
+        ("project".into(), "max_line_length = 120".into()),
+    ])
+    .unwrap();
+    assert_eq!(config.max_line_length, 120);
+    assert_eq!(config.indent_space, 4);
+    assert_eq!(config.line_wrap, LineWrap::Fill);
+    assert_eq!(origins["max_line_length"], "project");
+    assert_eq!(origins["indent_space"], "global");
+    assert_eq!(origins["line_wrap"], "default");
+}
+
+#[test]
+fn layers_name_the_invalid_one() {
+    let err =
+        Config::from_toml_layers(&[("project".into(), "max_line_lenght = 1".into())]).unwrap_err();
+    assert!(err.starts_with("project: "));
+}
+
+#[test]
+fn overrides_apply_in_order() {
+    let config = Config::from_toml(
+        r#"
+max_line_length = 80
+
+[[overrides]]
+files = ["slides/**"]
+max_line_length = 120
+line_wrap = false
+
+[[overrides]]
+files = ["slides/wide.typ"]
+max_line_length = 200
+"#,
+    )
+    .unwrap();
+    let paper = config.for_file(Path::new("paper/main.typ")).unwrap();
+    assert_eq!(paper.max_line_length, 80);
+    assert_eq!(paper.line_wrap, LineWrap::Fill);
+    let slides = config.for_file(Path::new("slides/intro.typ")).unwrap();
+    assert_eq!(slides.max_line_length, 120);
+    assert_eq!(slides.line_wrap, LineWrap::Off);
+    let wide = config.for_file(Path::new("./slides/wide.typ")).unwrap();
+    assert_eq!(wide.max_line_length, 200);
+    assert_eq!(wide.line_wrap, LineWrap::Off);
+}
+
+#[test]
+fn overrides_in_default_toml() {
+    let config = Config {
+        overrides: vec![Override {
+            files: vec!["slides/**".into()],
+            max_line_length: Some(120),
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let toml = toml::to_string_pretty(&config).unwrap();
+    assert_eq!(
+        Config::from_toml(&toml).unwrap().overrides,
+        config.overrides
+    );
+}
+
+#[test]
+fn insert_final_newline() {
+    let config = Config {
+        insert_final_newline: true,
+        ..Default::default()
+    };
+    assert_eq!(format("#f()", config.clone()), "#f()\n");
+    assert_eq!(format("#f()\n\n\n", config), "#f()\n");
+    assert_eq!(format("#f()", Config::default()), "#f()");
+}
+
+#[test]
+fn line_wrap_from_toml() {
+    let line_wrap = |s: &str| Config::from_toml(&format!("line_wrap = {s}")).map(|c| c.line_wrap);
+    assert_eq!(line_wrap("true"), Ok(LineWrap::Fill));
+    assert_eq!(line_wrap("false"), Ok(LineWrap::Off));
+    assert_eq!(line_wrap("\"sentence\""), Ok(LineWrap::Sentence));
+    assert_eq!(line_wrap("\"unwrap\""), Ok(LineWrap::Unwrap));
+    assert_eq!(line_wrap("\"preserve\""), Ok(LineWrap::Preserve));
+    assert!(line_wrap("\"sentences\"").is_err());
+    let toml = toml::to_string(&Config {
+        line_wrap: LineWrap::Sentence,
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(
+        Config::from_toml(&toml).unwrap().line_wrap,
+        LineWrap::Sentence
+    );
+}
@@ -0,0 +1,29 @@
+use super::*;
+
+#[test]
+fn only_what_changed() {
+    assert_eq!(
+        format_edits("#f(a,b)", Config::default()),
+        [(5..5, " ".into())]
+    );
+}
+
+#[test]
+fn nothing_when_formatted() {
+    assert!(format_edits("#f(a, b)\n\nsome text", Config::default()).is_empty());
+}
+
+#[test]
+fn far_apart_lines() {
+    assert_eq!(
+        format_edits("#let a  =  1\nsome text\n#f(a,b)", Config::default()),
+        [(7..8, "".into()), (9..10, "".into()), (28..28, " ".into())]
+    );
+}
+
+#[test]
+fn non_ascii() {
+    let input = "é à #f(a,b)";
+    let edits = format_edits(input, Config::default());
+    assert_eq!(edits, [(input.len() - 2..input.len() - 2, " ".into())]);
+}
@@ -0,0 +1,35 @@
+use super::*;
+
+#[test]
+fn reports_the_position() {
+    let errors = try_format("#f(a,b)\n#g(a,,b)", Config::default())
+        .unwrap_err()
+        .errors;
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].line, errors[0].column), (2, 6));
+}
+
+#[test]
+fn formats_without_errors() {
+    assert_eq!(
+        try_format("#f(a,b)", Config::default()),
+        Ok("#f(a, b)".to_string())
+    );
+}
+
+make_test!(
+    erroneous_args_kept,
+    "#f(a,b)\n#g(a,,b)\n#h(a,b)",
+    Config {
+        keep_erroneous: true,
+        ..Default::default()
+    }
+);
+make_test!(
+    erroneous_let_kept,
+    "#let x  =  (1,2)\n#let y  =  \nsome   text",
+    Config {
+        keep_erroneous: true,
+        ..Default::default()
+    }
+);
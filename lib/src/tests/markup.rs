@@ -43,3 +43,125 @@ test_eq!(
 test_eq!(equation_spaced, "aaa $ a b c $ bbb");
 test_eq!(last_space_conserved, "#[. ]");
 make_test!(last_space_conserved_as_space, "#[.\n]");
+
+// with words as long as the lines, every separator breaks, except before the
+// words that would start a list, enum, term or heading.
+make_test!(
+    no_list_marker_at_line_start,
+    "everything - understand + themselves -",
+    Config {
+        max_line_length: 10,
+        ..Default::default()
+    }
+);
+make_test!(
+    no_enum_or_term_marker_at_line_start,
+    "everything / understand 12. themselves 3.",
+    Config {
+        max_line_length: 10,
+        ..Default::default()
+    }
+);
+make_test!(
+    no_heading_marker_at_line_start,
+    "everything = understand == themselves",
+    Config {
+        max_line_length: 10,
+        ..Default::default()
+    }
+);
+
+const SENTENCES: &str = "This is the first sentence. It is short! Is this the third one? The fourth sentence of this paragraph is long enough to go over the max line length. It ends with e.g. an abbreviation, \"Quoted.\" And (a parenthesis.) Done.\n\nA new paragraph\nwith a line break. Another sentence.";
+
+make_test!(
+    line_wrap_sentence,
+    SENTENCES,
+    Config {
+        line_wrap: LineWrap::Sentence,
+        ..Default::default()
+    }
+);
+make_test!(
+    line_wrap_sentence_in_list,
+    "- First sentence of the item. Second sentence of the item.\n- Short. Item.",
+    Config {
+        line_wrap: LineWrap::Sentence,
+        ..Default::default()
+    }
+);
+make_test!(
+    line_wrap_sentence_in_heading,
+    "= Heading. Stays on one line.",
+    Config {
+        line_wrap: LineWrap::Sentence,
+        ..Default::default()
+    }
+);
+
+make_test!(
+    line_wrap_unwrap,
+    "A paragraph wrapped\nby the writer _with_\n*some* emphasis and a line that is long enough to go over the max line length.\n\nThe second one\nends with a break \\\nstays // a comment\nstays too\n- a list\n  item\n#let a = 1\nafter the let",
+    Config {
+        line_wrap: LineWrap::Unwrap,
+        ..Default::default()
+    }
+);
+make_test!(
+    line_wrap_unwrap_content_block,
+    "#box[\n  first line\n  second line\n]",
+    Config {
+        line_wrap: LineWrap::Unwrap,
+        ..Default::default()
+    }
+);
+
+make_test!(
+    line_wrap_preserve,
+    "Two roads diverged\nin a yellow wood, and sorry I could not travel both, and be one traveler, long I stood\nand looked down one\nas far as I could.\n\n- a list\n  with two lines",
+    Config {
+        line_wrap: LineWrap::Preserve,
+        ..Default::default()
+    }
+);
+
+make_test!(
+    cjk_kinsoku,
+    "漢字 「引用」 漢字 「引用」 漢字 。 漢字 、 漢字",
+    Config {
+        max_line_length: 10,
+        ..Default::default()
+    }
+);
+make_test!(
+    cjk_wide_characters,
+    "中文 中文 中文 中文 中文 中文",
+    Config {
+        max_line_length: 10,
+        ..Default::default()
+    }
+);
+make_test!(
+    cjk_newline_is_a_space,
+    "吾輩は猫である。\n名前はまだ無い。",
+    Config {
+        max_line_length: 40,
+        ..Default::default()
+    }
+);
+make_test!(
+    cjk_long_heading,
+    "= 吾輩は猫である。 名前はまだ無い。 どこで生れたかとんと見当がつかぬ。",
+    Config {
+        max_line_length: 20,
+        ..Default::default()
+    }
+);
+// not wrapped, a newline between the characters would be a space in typst.
+make_test!(
+    cjk_without_spaces,
+    "吾輩は猫である。名前はまだ無い。どこで生れたかとんと見当がつかぬ。",
+    Config {
+        max_line_length: 20,
+        ..Default::default()
+    }
+);
@@ -34,9 +34,10 @@ fn init() {
 
 /// This makes :
 /// - A snapshot test where you're prompted to say if you're snippet is nicely formatted.
-/// (see README.md)
+///   (see README.md)
 /// - A double format test (if an input is formatted twice it should give the same result)
 /// - An AST test (if an input is formatted, the output AST should be the same as the input).
+/// - An edits test (applying the edits to the input gives the formatted output).
 ///
 /// TODO : currently for the AST test, all Space and parbeak are skipped, maybe there is a better way.
 /// TODO : AST check when we had a trailing comma, find a way to allow it to be able to run test for these snippets too.
@@ -65,7 +66,7 @@ macro_rules! make_test {
                 println!("AST: {:?}",parse($input));
                 let input = $input;
                 let formatted = format(input, $config);
-                assert!(tests::parses_the_same(&input, &formatted));
+                assert!(verify(&input, &formatted));
             }
 
             #[test]
@@ -77,6 +78,17 @@ macro_rules! make_test {
                 let format_twice = format(&format_once, $config);
                 similar_asserts::assert_eq!(format_once, format_twice);
             }
+
+            #[test]
+            fn edits() {
+                init();
+                let input = $input;
+                let mut edited = input.to_string();
+                for (range, text) in format_edits(input, $config).into_iter().rev() {
+                    edited.replace_range(range, &text);
+                }
+                similar_asserts::assert_eq!(format(input, $config), edited);
+            }
         }
     };
 }
@@ -101,72 +113,65 @@ macro_rules! test_eq {
     };
 }
 
-// allowing modifying trailing comma's, text in markup, space everywhere
-// todo, check adding all text from one tree and another equal the same text.
-fn tree_are_equal(node: &LinkedNode, other_node: &LinkedNode) -> bool {
-    let should_ignore = |x: &LinkedNode| [Space, Parbreak, Comma, Text].contains(&x.kind());
-
-    let node_kind = node.kind();
-    let other_kind = other_node.kind();
-    if node_kind != other_kind {
-        debug!("kind differs! {:?}-{:?}", node_kind, other_kind);
-        return false;
-    }
+/// This makes a snapshot test of formatting only a range of the input, the
+/// result is the input with the replaced range swapped for the formatted text.
+///
+/// Also checks the replaced range contains the asked one and the AST doesn't change.
+macro_rules! make_range_test {
+    ($test_name:ident, $input:expr, $range:expr $(,)?) => {
+        mod $test_name {
+            use super::*;
 
-    if (node.text() != other_node.text()) && !should_ignore(node) {
-        debug!(
-            "kind ok {:?}\ntext differ:{:?}-{:?}",
-            node.kind(),
-            node.text(),
-            other_node.text()
-        );
-        return false;
-    }
+            #[test]
+            fn snapshot() {
+                init();
+                let input = $input;
+                let range = $range;
+                let (replaced, text) = format_range(input, range.clone(), Config::default());
+                assert!(replaced.start <= range.start && range.end <= replaced.end);
+                let mut formatted = input.to_string();
+                formatted.replace_range(replaced, &text);
+                assert!(verify(&input, &formatted));
+                insta::with_settings!({description => format!("INPUT\n===\n{input:?}\n===\n{input}\n===\nRANGE {range:?}: {:?}\n===\nFORMATTED\n===\n{formatted}", &input[range.clone()])}, {
+                    insta::assert_debug_snapshot!(formatted);
+                });
+            }
+        }
+    };
+}
 
-    let fchildren = node.children().filter(|x| !should_ignore(x)).collect_vec();
-    let fchildren_oth = other_node
-        .children()
-        .filter(|x| !should_ignore(x))
-        .collect_vec();
-    if fchildren.len() != fchildren_oth.len() {
-        debug!(
-            "children count differ! {:?}\n{:?}",
-            fchildren, fchildren_oth
-        );
-        return false;
-    }
-    if node
-        .children()
-        .filter(|x| !should_ignore(x))
-        .zip(other_node.children().filter(|x| !should_ignore(x)))
-        .any(|(c, oth)| !tree_are_equal(&c, &oth))
-    {
-        return false;
-    }
-    true
+#[test]
+fn comma_gets_ignored_in_comparison() {
+    assert!(verify("#f(1,2,)", "#f(1,2)"));
+    assert!(verify("#f(1,{g(1,2,3,)},)", "#f(1,{g(1,2,3)})"));
 }
 
-#[instrument(skip_all)]
-fn parses_the_same(s: &str, oth: &str) -> bool {
-    let parse1 = parse(s);
-    let lkn = LinkedNode::new(&parse1);
-    let parse2 = parse(oth);
-    let lkn_oth = LinkedNode::new(&parse2);
-    debug!("{:?}", parse1);
-    debug!("{:?}", parse2);
-    tree_are_equal(&lkn, &lkn_oth)
+#[test]
+fn words_get_compared() {
+    assert!(verify("some  words\nwrapped", "some words wrapped"));
+    assert!(!verify("some words", "somewords"));
+    assert!(!verify("some words", "some other words"));
 }
 
 #[test]
-fn comma_gets_ignored_in_comparison() {
-    assert!(parses_the_same("#f(1,2,)", "#f(1,2)"));
-    assert!(parses_the_same("#f(1,{g(1,2,3,)},)", "#f(1,{g(1,2,3)})"));
+fn newlines_between_cjk_are_spaces() {
+    assert!(verify("吾輩は\n猫である。", "吾輩は 猫である。"));
+    assert!(!verify("吾輩は\n猫である。", "吾輩は猫である。"));
+    assert!(!verify("吾輩は猫である。", "吾輩は\n猫である。"));
+    assert!(verify("吾輩は\ncats", "吾輩は cats"));
+    assert!(!verify("吾輩は\ncats", "吾輩はcats"));
 }
 
 mod code_block;
 mod comments;
 mod conditionals;
+mod config;
+mod edits;
+mod errors;
 mod lists;
 mod markup;
+mod newlines;
 mod params;
+mod range;
 mod snippets;
+mod tabs;
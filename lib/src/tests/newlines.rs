@@ -0,0 +1,75 @@
+use super::*;
+
+#[test]
+fn crlf_is_kept() {
+    let formatted = format("#f(a,b)\r\n\r\n#{\r\nlet a = 1\r\n}\r\n", Config::default());
+    assert_eq!(formatted, "#f(a, b)\r\n\r\n#{\r\n  let a = 1\r\n}\r\n");
+}
+
+#[test]
+fn newline_style_converts() {
+    let crlf = Config {
+        newline_style: NewlineStyle::Crlf,
+        ..Default::default()
+    };
+    assert_eq!(format("#f(a,b)\n#g()\n", crlf), "#f(a, b)\r\n#g()\r\n");
+    let lf = Config {
+        newline_style: NewlineStyle::Lf,
+        ..Default::default()
+    };
+    assert_eq!(format("#f(a,b)\r\n#g()\r\n", lf), "#f(a, b)\n#g()\n");
+}
+
+#[test]
+fn newline_style_converts_raw_text() {
+    let input = "#f(a,b)\r\n```\r\nfn main() {\r\n}\r\n```\r\n/* a\r\nb */ #\"c\r\nd\"\r\n";
+    let lf = Config {
+        newline_style: NewlineStyle::Lf,
+        ..Default::default()
+    };
+    let formatted = format(input, lf);
+    assert_eq!(
+        formatted,
+        input.replace("\r\n", "\n").replace("f(a,b)", "f(a, b)")
+    );
+    assert!(verify(input, &formatted));
+    let crlf = Config {
+        newline_style: NewlineStyle::Crlf,
+        ..Default::default()
+    };
+    let formatted = format(&input.replace("\r\n", "\n"), crlf);
+    assert_eq!(formatted, input.replace("f(a,b)", "f(a, b)"));
+    assert!(verify(input, &formatted));
+}
+
+#[test]
+fn bom_is_kept() {
+    assert_eq!(
+        format("\u{feff}#f(a,b)\r\n", Config::default()),
+        "\u{feff}#f(a, b)\r\n"
+    );
+    assert_eq!(format("#f(a,b)", Config::default()), "#f(a, b)");
+}
+
+#[test]
+fn range_keeps_crlf() {
+    let input = "#f(a,b)\r\n\r\n#{\r\nlet a = f(first_argument, second_argument, third_argument, fourth_argument, fifth)\r\n}";
+    let (replaced, text) = format_range(input, 15..20, Config::default());
+    assert!(!text.replace("\r\n", "").contains('\n'));
+    assert!(text.contains("\r\n"));
+    assert_eq!(&input[..replaced.start], "#f(a,b)\r\n\r\n#{\r\n");
+}
+
+#[test]
+fn range_converts_raw_text() {
+    let input = "#{\r\nf(a,b, ```\r\nc\r\n```)\r\n}\r\n";
+    let lf = Config {
+        newline_style: NewlineStyle::Lf,
+        ..Default::default()
+    };
+    let (replaced, text) = format_range(input, 5..8, lf);
+    assert!(!text.contains('\r'));
+    let mut edited = input.to_string();
+    edited.replace_range(replaced, &text);
+    assert!(verify(input, &edited));
+}
@@ -92,3 +92,17 @@ test_eq!(
     "f[ this loooooooooooooooooooooooooooong text is not supposed to not be indented
 at all ]"
 );
+make_test!(
+    nested_call_breaks_outer_first,
+    "#figure(table(columns: (auto, 1fr, 1fr), [Name], [Description of the thing], [Value of the thing]), caption: [A table])"
+);
+make_test!(
+    nested_call_inner_fits_once_broken,
+    "#{
+  let result = some-function(first-argument, second-argument, third-argument-that-is-long)
+}"
+);
+make_test!(
+    binary_breaks_in_parenthesized,
+    "#let f(x) = (aaaaaaaaaaaaaaaaaaaaaaaa + bbbbbbbbbbbbbbbbbbbbbbbbbbbbbb + cccccccccccccccccccccccccc)"
+);
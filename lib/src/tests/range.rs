@@ -0,0 +1,28 @@
+use super::*;
+
+make_range_test!(
+    only_the_call,
+    "#f(a,b)   and   some   text\n\n#g(a,b)",
+    1..3,
+);
+make_range_test!(
+    only_the_paragraph,
+    "First   paragraph   stays.\n\nThis  one is   formatted, it's long enough to be wrapped because it goes over the max line length.\n\nLast   one stays.",
+    40..50,
+);
+make_range_test!(
+    keeps_the_indentation,
+    "#{\n  let a  =  1\n  let b = f(first_argument, second_argument, third_argument, fourth_argument, fifth)\n}",
+    20..30,
+);
+make_range_test!(
+    only_the_selected_statements,
+    "#{\n  let a  =  1\n  let b  =  2\n  let c  =  3\n}",
+    17..25,
+);
+make_range_test!(
+    disabled_stays,
+    "// typstfmt::off\n#f(a,b)\n// typstfmt::on\n#f(a,b)",
+    19..20,
+);
+make_range_test!(everything, "#f(a,b)\n\n#g(a,b)", 0..16);
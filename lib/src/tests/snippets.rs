@@ -3,6 +3,9 @@ use super::*;
 test_eq!(let_stmt, "#let ident = variable");
 test_eq!(let_stmt_period_terminated, "#let ident = variable;");
 make_test!(let_stmt_no_spacing, "#let ident=variable");
+test_eq!(str_spaces, "#let a = \"a  b  \"");
+test_eq!(raw_trailing_spaces, "```\na  \nb \n```\n#f(`c  `)");
+make_test!(multiline_str, "#{\n  f(\"a\n  b\",\n    \"c\n  d\")\n}");
 make_test!(ten_adds, &format!("#{{{}1}}", "1+".repeat(10)));
 make_test!(thirty_adds, &format!("#{{{}1}}", "1+".repeat(30)));
 test_eq!(not_in, "#let page_turned = page not in header_pages");
@@ -39,7 +42,7 @@ test_eq!(
     line_wrap_off,
     "a very very very very very very very very very very very very very long line",
     Config {
-        line_wrap: false,
+        line_wrap: LineWrap::Off,
         max_line_length: 50,
         ..Default::default()
     }
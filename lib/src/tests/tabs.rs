@@ -0,0 +1,35 @@
+use super::*;
+
+make_test!(tab_indented, "#{\n\tlet a = 1\n\tlet b = 2\n}");
+make_test!(tab_in_string, "#let a = \"a\tb\"\n#{\n\tf(\"\t\")\n}");
+make_test!(
+    tab_in_raw,
+    "```\nfn main() {\n\tprintln!()\n}\n```\n\n#{\n\t`a\tb`\n}"
+);
+make_test!(
+    tab_in_comments,
+    "#{\n\tlet a = 1 // a\tb\n\t/* c\n\t\td */\n}\n\n// e\tf\n/* g\th */"
+);
+make_test!(
+    indent_style_tab,
+    "#{\nlet a = f(first_argument, second_argument, third_argument, fourth_argument)\n}",
+    Config {
+        indent_style: IndentStyle::Tab,
+        ..Default::default()
+    }
+);
+make_test!(
+    indent_style_tab_odd_columns,
+    "#{\n  let a = f(first_argument, second_argument, third_argument, fourth_argument)\n}",
+    Config {
+        indent_style: IndentStyle::Tab,
+        indent_space: 4,
+        max_line_length: 40,
+        ..Default::default()
+    }
+);
+make_range_test!(
+    range_after_tabs,
+    "#{\n\tlet a  =  \"\t\"\n\tlet b  =  2\n}",
+    18..22,
+);
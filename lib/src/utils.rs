@@ -1,5 +1,4 @@
 use super::*;
-use unicode_segmentation::UnicodeSegmentation;
 
 /// like next sibling but doesn't skip trivia.
 pub(crate) fn next_sibling_or_trivia<'a>(node: &LinkedNode<'a>) -> Option<LinkedNode<'a>> {
@@ -43,7 +42,6 @@ pub(crate) enum Btype {
     #[default]
     Markup,
     Math,
-    Code,
 }
 
 #[instrument(ret, skip_all)]
@@ -138,28 +136,3 @@ pub(crate) fn prev_is_ignoring(node: &LinkedNode, is: SyntaxKind, ignoring: &[Sy
     debug!("next is: {:?}", n.as_ref().map(|x| x.kind()));
     n.is_some_and(|n| is == n.kind())
 }
-
-pub(crate) fn max_line_length(s: &str) -> usize {
-    s.lines()
-        .map(|l| l.trim_start().graphemes(true).count())
-        .max()
-        .unwrap_or(0)
-}
-
-pub(crate) fn last_line_length(s: &str) -> usize {
-    s.split('\n')
-        .last()
-        .unwrap_or("")
-        .trim_start()
-        .graphemes(true)
-        .count()
-}
-
-pub(crate) fn first_line_length(s: &str) -> usize {
-    s.split('\n')
-        .next()
-        .unwrap_or("")
-        .trim_start()
-        .graphemes(true)
-        .count()
-}
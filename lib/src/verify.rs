@@ -0,0 +1,91 @@
+use super::*;
+
+/// Checks formatting didn't change the meaning of the document, both sources
+/// must parse to the same syntax tree, allowing changes to the trailing commas,
+/// the spaces and how the markup is broken in lines, and have the same words.
+/// `\r\n` and `\n` are the same newline, also in raw text, strings and
+/// comments, so the line endings can be converted.
+///
+/// If this is false for the output of [format], it's a bug.
+#[instrument(skip_all)]
+pub fn verify(original: &str, formatted: &str) -> bool {
+    let (original, formatted) = (
+        original.replace("\r\n", "\n"),
+        formatted.replace("\r\n", "\n"),
+    );
+    let parse1 = parse(&original);
+    let lkn = LinkedNode::new(&parse1);
+    let parse2 = parse(&formatted);
+    let lkn_oth = LinkedNode::new(&parse2);
+    debug!("{:?}", parse1);
+    debug!("{:?}", parse2);
+    if !tree_are_equal(&lkn, &lkn_oth) {
+        return false;
+    }
+    let mut words = String::new();
+    let mut words_oth = String::new();
+    push_words(&lkn, &mut words);
+    push_words(&lkn_oth, &mut words_oth);
+    if !words.split_whitespace().eq(words_oth.split_whitespace()) {
+        debug!("words differ! {:?}\n{:?}", words, words_oth);
+        return false;
+    }
+    true
+}
+
+// allowing modifying trailing comma's, text in markup, space everywhere
+// the text in markup is compared by push_words.
+fn tree_are_equal(node: &LinkedNode, other_node: &LinkedNode) -> bool {
+    let should_ignore = |x: &LinkedNode| [Space, Parbreak, Comma, Text].contains(&x.kind());
+
+    let node_kind = node.kind();
+    let other_kind = other_node.kind();
+    if node_kind != other_kind {
+        debug!("kind differs! {:?}-{:?}", node_kind, other_kind);
+        return false;
+    }
+
+    if (node.text() != other_node.text()) && !should_ignore(node) {
+        debug!(
+            "kind ok {:?}\ntext differ:{:?}-{:?}",
+            node.kind(),
+            node.text(),
+            other_node.text()
+        );
+        return false;
+    }
+
+    let fchildren = node.children().filter(|x| !should_ignore(x)).collect_vec();
+    let fchildren_oth = other_node
+        .children()
+        .filter(|x| !should_ignore(x))
+        .collect_vec();
+    if fchildren.len() != fchildren_oth.len() {
+        debug!(
+            "children count differ! {:?}\n{:?}",
+            fchildren, fchildren_oth
+        );
+        return false;
+    }
+    if node
+        .children()
+        .filter(|x| !should_ignore(x))
+        .zip(other_node.children().filter(|x| !should_ignore(x)))
+        .any(|(c, oth)| !tree_are_equal(&c, &oth))
+    {
+        return false;
+    }
+    true
+}
+
+/// the text of the markup, with a space for each space or paragraph break.
+///
+/// A newline is a space too between chinese or japanese characters, typst 0.7
+/// renders it like any other one.
+fn push_words(node: &LinkedNode, words: &mut String) {
+    match node.kind() {
+        Text => words.push_str(node.text()),
+        Space | Parbreak => words.push(' '),
+        _ => node.children().for_each(|child| push_words(&child, words)),
+    }
+}
@@ -0,0 +1,277 @@
+//! Finding the config of each file.
+//!
+//! The config is made of layers, each overriding the keys it sets: the
+//! defaults, the `.editorconfig` properties of the file, the global config,
+//! the project config and the `--set` options.
+//!
+//! The project config is the closest `typstfmt.toml`, or `typst.toml` package
+//! manifest with a `[tool.typstfmt]` table, walking up from the directory of
+//! the file, stopping at the root of the repository.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use ec4rs::property::{EndOfLine, FinalNewline, IndentSize, IndentStyle, MaxLineLen};
+use typstfmt_lib::Config;
+
+use crate::{error::CliError, Verbose, CONFIG_FILE_NAME};
+
+const MANIFEST_FILE_NAME: &str = "typst.toml";
+
+/// a config with the layer each key comes from.
+type Layered = (Config, BTreeMap<String, String>);
+
+/// a loaded config with the files it was read from, it's loaded again when one
+/// of them changes.
+struct Loaded {
+    layered: Layered,
+    files: Vec<(PathBuf, Option<Stamp>)>,
+}
+
+/// when a file was modified and its length, `None` if it doesn't exist.
+type Stamp = (SystemTime, u64);
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+pub(crate) struct Configs {
+    /// given with `--config`, used for every file.
+    explicit: Option<PathBuf>,
+    /// added to the exclude globs of every config.
+    exclude: Vec<String>,
+    /// the toml of the `--set` options.
+    set: String,
+    verbose: Verbose,
+    /// the global config file, there is none if the config directory of the
+    /// user isn't known.
+    pub(crate) global: Option<PathBuf>,
+    /// by the path of the project config file and the toml of the
+    /// `.editorconfig` properties.
+    loaded: HashMap<(Option<PathBuf>, String), Loaded>,
+}
+
+impl Configs {
+    pub(crate) fn new(
+        explicit: Option<PathBuf>,
+        exclude: Vec<String>,
+        set: String,
+        verbose: Verbose,
+    ) -> Self {
+        Self {
+            explicit,
+            exclude,
+            set,
+            verbose,
+            global: confy::get_configuration_file_path("typstfmt", None).ok(),
+            loaded: HashMap::new(),
+        }
+    }
+
+    /// the config of a file, or of stdin for a directory, with the overrides
+    /// matching it.
+    pub(crate) fn for_path(&mut self, path: &Path) -> Result<Config, CliError> {
+        let (file, relative) = self.find_relative(path)?;
+        self.load(file, path)?
+            .0
+            .for_file(&relative)
+            .map_err(invalid_glob)
+    }
+
+    /// prints the config of the path as toml, with the layer each value comes from.
+    pub(crate) fn print(&mut self, path: &Path) -> Result<(), CliError> {
+        let (file, relative) = self.find_relative(path)?;
+        let (config, origins) = self.load(file, path)?;
+        let mut origins = origins.clone();
+        for o in config.overrides_for(&relative).map_err(invalid_glob)? {
+            let toml::Value::Table(table) =
+                toml::Value::try_from(o).map_err(|e| CliError::Config(e.to_string()))?
+            else {
+                unreachable!("an override is a table");
+            };
+            for key in table.keys().filter(|key| *key != "files") {
+                origins.insert(key.clone(), format!("override for {:?}", o.files));
+            }
+        }
+        let config = config.for_file(&relative).map_err(invalid_glob)?;
+        let toml::Value::Table(table) =
+            toml::Value::try_from(config).map_err(|e| CliError::Config(e.to_string()))?
+        else {
+            unreachable!("the config is a table");
+        };
+        for (key, value) in table {
+            println!("{key} = {value} # {}", origins[&key]);
+        }
+        Ok(())
+    }
+
+    /// true if the config of the file includes it.
+    pub(crate) fn includes(&mut self, path: &Path) -> Result<bool, CliError> {
+        let (file, relative) = self.find_relative(path)?;
+        self.load(file, path)?
+            .0
+            .includes(&relative)
+            .map_err(invalid_glob)
+    }
+
+    /// the project config file for the path with the path relative to its
+    /// directory, the globs of the config are relative to it.
+    fn find_relative(&self, path: &Path) -> Result<(Option<PathBuf>, PathBuf), CliError> {
+        let path = absolute(path)?;
+        let file = self.find(&path);
+        let base = match &file {
+            Some(file) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => absolute(Path::new("."))?,
+        };
+        let relative = path.strip_prefix(&base).unwrap_or(&path).to_path_buf();
+        Ok((file, relative))
+    }
+
+    /// the project config file for the absolute path, `None` if there is none.
+    fn find(&self, path: &Path) -> Option<PathBuf> {
+        if let Some(explicit) = &self.explicit {
+            return Some(explicit.clone());
+        }
+        let dir = if path.is_dir() { path } else { path.parent()? };
+        for dir in dir.ancestors() {
+            let file = dir.join(CONFIG_FILE_NAME);
+            if file.is_file() {
+                return Some(file);
+            }
+            let manifest = dir.join(MANIFEST_FILE_NAME);
+            if std::fs::read_to_string(&manifest).is_ok_and(|s| tool_table(&s).is_some()) {
+                return Some(manifest);
+            }
+            if dir.join(".git").exists() {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn load(&mut self, file: Option<PathBuf>, path: &Path) -> Result<&Layered, CliError> {
+        let key = (file, editorconfig_toml(path)?);
+        let fresh = self
+            .loaded
+            .get(&key)
+            .is_some_and(|loaded| loaded.files.iter().all(|(path, old)| stamp(path) == *old));
+        if !fresh {
+            let (file, editorconfig) = &key;
+            let mut layers = vec![("editorconfig".to_string(), editorconfig.clone())];
+            let mut files = vec![];
+            for (name, path) in [("global", self.global.as_ref()), ("project", file.as_ref())] {
+                if let Some(path) = path {
+                    files.push((path.clone(), stamp(path)));
+                }
+                let Some(path) = path.filter(|path| path.is_file()) else {
+                    continue;
+                };
+                self.verbose
+                    .print(format_args!("Using the config file {path:?}"));
+                let mut buf = std::fs::read_to_string(path).map_err(|err| {
+                    CliError::Io(format!("Failed to read config file {path:?}: {err}"))
+                })?;
+                if path
+                    .file_name()
+                    .is_some_and(|name| name == MANIFEST_FILE_NAME)
+                {
+                    buf = tool_table(&buf).unwrap_or_default();
+                }
+                layers.push((format!("{name} {}", path.display()), buf));
+            }
+            layers.push(("--set".to_string(), self.set.clone()));
+            let (mut config, mut origins) = Config::from_toml_layers(&layers).map_err(|e| {
+                CliError::Config(format!(
+                    "Invalid config in {e}.\nYou can use -C to create a default config file."
+                ))
+            })?;
+            if !self.exclude.is_empty() {
+                config.exclude.extend(self.exclude.iter().cloned());
+                origins.insert("exclude".to_string(), "--exclude".to_string());
+            }
+            let layered = (config, origins);
+            self.loaded.insert(key.clone(), Loaded { layered, files });
+        }
+        Ok(&self.loaded[&key].layered)
+    }
+}
+
+/// the `[tool.typstfmt]` table of a package manifest, as the toml of a config file.
+pub(crate) fn tool_table(manifest: &str) -> Option<String> {
+    let manifest: toml::Table = manifest.parse().ok()?;
+    let table = manifest.get("tool")?.get("typstfmt")?.as_table()?;
+    toml::to_string(table).ok()
+}
+
+/// the toml of the `.editorconfig` properties of a file that have a setting,
+/// empty for a directory.
+pub(crate) fn editorconfig_toml(path: &Path) -> Result<String, CliError> {
+    let path = absolute(path)?;
+    if path.is_dir() {
+        return Ok(String::new());
+    }
+    let mut properties = ec4rs::properties_of(&path)
+        .map_err(|e| CliError::Config(format!("Invalid .editorconfig for {path:?}: {e}")))?;
+    // `indent_size = tab` takes the `tab_width`.
+    properties.use_fallbacks();
+    let mut table = toml::Table::new();
+    if let Ok(IndentSize::Value(size)) = properties.get::<IndentSize>() {
+        table.insert("indent_space".into(), (size as i64).into());
+    }
+    if let Ok(style) = properties.get::<IndentStyle>() {
+        let style = match style {
+            IndentStyle::Tabs => "tab",
+            IndentStyle::Spaces => "space",
+        };
+        table.insert("indent_style".into(), style.into());
+    }
+    if let Ok(MaxLineLen::Value(len)) = properties.get::<MaxLineLen>() {
+        table.insert("max_line_length".into(), (len as i64).into());
+    }
+    let newline_style = match properties.get::<EndOfLine>() {
+        Ok(EndOfLine::Lf) => Some("lf"),
+        Ok(EndOfLine::CrLf) => Some("crlf"),
+        // `\r` alone isn't supported.
+        Ok(EndOfLine::Cr) | Err(_) => None,
+    };
+    if let Some(style) = newline_style {
+        table.insert("newline_style".into(), style.into());
+    }
+    if let Ok(FinalNewline::Value(final_newline)) = properties.get::<FinalNewline>() {
+        table.insert("insert_final_newline".into(), final_newline.into());
+    }
+    Ok(table.to_string())
+}
+
+/// the toml for a `--set key=value` option, the value is a string if it isn't
+/// valid toml.
+pub(crate) fn set_to_toml(option: &str) -> Result<String, String> {
+    let (key, value) = option
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got {option:?}"))?;
+    let (key, value) = (key.trim(), value.trim());
+    let line = format!("{key} = {value}");
+    if line.parse::<toml::Table>().is_ok() {
+        return Ok(line);
+    }
+    Ok(format!(
+        "{key} = {}",
+        toml::Value::String(value.to_string())
+    ))
+}
+
+/// the path from the root, without `.` or `..` in it if it exists.
+fn absolute(path: &Path) -> Result<PathBuf, CliError> {
+    let path = std::env::current_dir()
+        .map_err(|err| CliError::Io(format!("Couldn't get the current directory: {err}")))?
+        .join(path);
+    Ok(path.canonicalize().unwrap_or(path))
+}
+
+fn invalid_glob(e: String) -> CliError {
+    CliError::Config(format!("Invalid glob in the config: {e}"))
+}
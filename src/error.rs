@@ -0,0 +1,50 @@
+//! The errors of the binary, the ones of a file are reported on stderr and
+//! the other files are still formatted.
+//!
+//! Each kind of error has its exit code, the highest one is used when several
+//! happened.
+
+use std::fmt;
+
+/// a file isn't formatted in check mode, or formatting it is a bug.
+pub(crate) const NEEDS_FORMATTING: i32 = 1;
+pub(crate) const IO_ERROR: i32 = 2;
+pub(crate) const INVALID_CONFIG: i32 = 3;
+
+#[derive(Debug)]
+pub(crate) enum CliError {
+    /// reading or writing a file, or a terminal, failed.
+    Io(String),
+    /// the config or the command line options are invalid.
+    Config(String),
+    /// formatting a file changed its meaning, it's left as is.
+    Bug(String),
+}
+
+impl CliError {
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Io(_) => IO_ERROR,
+            CliError::Config(_) => INVALID_CONFIG,
+            CliError::Bug(_) => NEEDS_FORMATTING,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Io(message) | CliError::Config(message) | CliError::Bug(message) => {
+                write!(f, "{message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<lexopt::Error> for CliError {
+    fn from(e: lexopt::Error) -> Self {
+        CliError::Config(format!("{e}\nuse -h or --help"))
+    }
+}
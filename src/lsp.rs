@@ -0,0 +1,265 @@
+//! A language server speaking over stdio, started with `typstfmt --lsp`.
+//!
+//! The documents are synced fully. The config of each document is found like
+//! the binary does, the configs are kept but read again when their files
+//! change, so edits to `typstfmt.toml` apply right away.
+
+use std::{collections::HashMap, error::Error, path::PathBuf};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    },
+    request::{Formatting, OnTypeFormatting, RangeFormatting, Request as _},
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    DocumentFormattingParams, DocumentOnTypeFormattingOptions, DocumentOnTypeFormattingParams,
+    DocumentRangeFormattingParams, InitializeParams, OneOf, Position, Range, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+};
+use typstfmt_lib::{format_edits, format_range, verify, Config};
+
+use crate::{config::Configs, Verbose, ISSUES};
+
+type LspResult<T> = Result<T, Box<dyn Error + Sync + Send>>;
+
+struct Server {
+    /// where `typstfmt.toml` is looked for when the document isn't a file.
+    root: PathBuf,
+    documents: HashMap<Url, String>,
+    configs: Configs,
+}
+
+/// Runs the server until the client asks it to exit.
+pub(crate) fn run() -> LspResult<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        document_range_formatting_provider: Some(OneOf::Left(true)),
+        document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+            first_trigger_character: "}".to_owned(),
+            more_trigger_character: Some(vec!["]".to_owned(), ")".to_owned()]),
+        }),
+        ..Default::default()
+    };
+    let params = connection.initialize(serde_json::to_value(capabilities)?)?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+
+    #[allow(deprecated)]
+    let root = params
+        .workspace_folders
+        .and_then(|folders| folders.into_iter().next())
+        .map(|folder| folder.uri)
+        .or(params.root_uri)
+        .and_then(|uri| uri.to_file_path().ok())
+        .unwrap_or_default();
+    let mut server = Server {
+        root,
+        documents: HashMap::new(),
+        configs: Configs::new(None, vec![], String::new(), Verbose::Off),
+    };
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                let response = server.handle_request(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                let method = notification.method.clone();
+                // the client doesn't wait for an answer, it's only logged.
+                if let Err(e) = server.handle_notification(notification) {
+                    eprintln!("Ignored the {method} notification: {e}");
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    // the writer thread stops once the sender is dropped.
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+impl Server {
+    fn handle_request(&mut self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            Formatting::METHOD => serde_json::from_value(request.params)
+                .map_err(Into::into)
+                .and_then(|params| self.formatting(params)),
+            RangeFormatting::METHOD => serde_json::from_value(request.params)
+                .map_err(Into::into)
+                .and_then(|params| self.range_formatting(params)),
+            OnTypeFormatting::METHOD => serde_json::from_value(request.params)
+                .map_err(Into::into)
+                .and_then(|params| self.on_type_formatting(params)),
+            method => {
+                return Response::new_err(
+                    id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unhandled method: {method}"),
+                )
+            }
+        };
+        match result {
+            Ok(edits) => Response::new_ok(id, edits),
+            Err(e) => Response::new_err(id, ErrorCode::RequestFailed as i32, e.to_string()),
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> LspResult<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.documents
+                    .insert(params.text_document.uri, params.text_document.text);
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // the sync is full, the last change is the whole document.
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(params.text_document.uri, change.text);
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                self.documents.remove(&params.text_document.uri);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// the config of the document, the one of the root of the workspace if it
+    /// isn't a file.
+    fn config(&mut self, uri: &Url) -> LspResult<Config> {
+        let path = uri.to_file_path().unwrap_or_else(|()| self.root.clone());
+        Ok(self.configs.for_path(&path)?)
+    }
+
+    fn document(&self, uri: &Url) -> LspResult<&str> {
+        Ok(self
+            .documents
+            .get(uri)
+            .ok_or_else(|| format!("document {uri} isn't opened"))?)
+    }
+
+    fn formatting(&mut self, params: DocumentFormattingParams) -> LspResult<Vec<TextEdit>> {
+        let config = self.config(&params.text_document.uri)?;
+        let text = self.document(&params.text_document.uri)?;
+        let edits = format_edits(text, config);
+        check_meaning(text, &apply(text, &edits))?;
+        Ok(edits
+            .into_iter()
+            .map(|(range, new_text)| TextEdit::new(to_lsp_range(text, range), new_text))
+            .collect())
+    }
+
+    fn range_formatting(
+        &mut self,
+        params: DocumentRangeFormattingParams,
+    ) -> LspResult<Vec<TextEdit>> {
+        let config = self.config(&params.text_document.uri)?;
+        let text = self.document(&params.text_document.uri)?;
+        let range = offset(text, params.range.start)..offset(text, params.range.end);
+        Ok(edit_range(text, range, config)?.into_iter().collect())
+    }
+
+    /// formats the node closed by the typed delimiter.
+    fn on_type_formatting(
+        &mut self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> LspResult<Vec<TextEdit>> {
+        let position = params.text_document_position;
+        let config = self.config(&position.text_document.uri)?;
+        let text = self.document(&position.text_document.uri)?;
+        let end = offset(text, position.position);
+        let Some(start) = end.checked_sub(params.ch.len()) else {
+            return Ok(vec![]);
+        };
+        if text.get(start..end) != Some(params.ch.as_str()) {
+            return Ok(vec![]);
+        }
+        Ok(edit_range(text, start..end, config)?.into_iter().collect())
+    }
+}
+
+/// the edit formatting the nodes covering the range, if it changes something.
+fn edit_range(
+    text: &str,
+    range: std::ops::Range<usize>,
+    config: Config,
+) -> LspResult<Option<TextEdit>> {
+    let (replaced, new_text) = format_range(text, range, config);
+    if text[replaced.clone()] == new_text {
+        return Ok(None);
+    }
+    check_meaning(text, &apply(text, &[(replaced.clone(), new_text.clone())]))?;
+    Ok(Some(TextEdit::new(to_lsp_range(text, replaced), new_text)))
+}
+
+/// refuses the formatted document if its meaning changed, like the binary
+/// does, the client would replace the document with it.
+pub(crate) fn check_meaning(text: &str, formatted: &str) -> LspResult<()> {
+    if !verify(text, formatted) {
+        return Err(format!(
+            "Formatting changed the meaning of the document, it was left as is.\n\
+             This is a bug, please report it at {ISSUES} with the document."
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// the text with the edits, they're sorted and don't overlap.
+pub(crate) fn apply(text: &str, edits: &[(std::ops::Range<usize>, String)]) -> String {
+    let mut res = String::new();
+    let mut end = 0;
+    for (range, new_text) in edits {
+        res.push_str(&text[end..range.start]);
+        res.push_str(new_text);
+        end = range.end;
+    }
+    res.push_str(&text[end..]);
+    res
+}
+
+/// the byte offset of a position, the characters of a position are counted
+/// in utf-16 code units, a position after the end of its line is at its end.
+pub(crate) fn offset(text: &str, position: Position) -> usize {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum();
+    let line = &text[line_start..];
+    let mut units = 0;
+    for (idx, c) in line.char_indices() {
+        let line_end = c == '\n' || line[idx..].starts_with("\r\n");
+        if units >= position.character as usize || line_end {
+            return line_start + idx;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+pub(crate) fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    let character = before[line_start..].encode_utf16().count();
+    Position::new(line as u32, character as u32)
+}
+
+fn to_lsp_range(text: &str, range: std::ops::Range<usize>) -> Range {
+    Range::new(position(text, range.start), position(text, range.end))
+}
@@ -2,33 +2,75 @@
 
 use std::{
     ffi::OsString,
-    fs::File,
-    io::{stdin, stdout, Read, Write},
+    fs::{self, File},
+    io::{self, stdin, stdout, Read, Write},
+    path::{Path, PathBuf},
 };
 
+use config::Configs;
+use error::{CliError, NEEDS_FORMATTING};
+use ignore::WalkBuilder;
 use lexopt::prelude::*;
-use typstfmt_lib::{format, Config};
+use report::{ReportFormat, Unformatted};
+use similar::{ChangeTag, TextDiff};
+use typstfmt_lib::{format, verify, Config};
+
+mod config;
+mod error;
+mod lsp;
+mod report;
 
 const VERSION: &str = env!("TYPSTFMT_VERSION");
 const CONFIG_FILE_NAME: &str = "typstfmt.toml";
+const IGNORE_FILE_NAME: &str = ".typstfmtignore";
+const DEFAULT_BACKUP_SUFFIX: &str = ".bak";
+const ISSUES: &str = "https://github.com/astrale-sharp/typstfmt/issues";
 const HELP: &str = r#"Format Typst code
 
 usage: typstfmt [options] [file...]
 
 If no file is specified, stdin will be used.
+Directories are walked to find the .typ files, skipping the ones ignored by
+.gitignore, .ignore or .typstfmtignore files.
 Files will be overwritten unless --output is passed.
 
+The errors of a file are printed and the other files still formatted, the exit
+code is 1 if a file needs formatting, 2 for an I/O error and 3 for an invalid
+config or option, the highest one if there are several.
+
 Options:
         -o, --output                If not specified, files will be overwritten. '-' for stdout.
         --stdout                    Same as `--output -` (Deprecated, here for compatibility).
+        --backup[=SUFFIX]           Before overwriting a file, copies it to its path with the
+                                    suffix, `.bak` by default, replacing the previous copy.
         --check                     Run in 'check' mode. Exits with 0 if input is
                                     formatted correctly. Exits with 1 if formatting is required.
+        --report-format FORMAT      With --check, prints a report of the unformatted files
+                                    with the first line to change, FORMAT is json, checkstyle
+                                    or github (annotations for github actions). Implies --check,
+                                    the --verbose messages are printed on stderr.
+        --diff                      Like --check but prints a unified diff of the changes
+                                    formatting would make to each file.
+        --no-color                  Prints the diffs without colors, also when the NO_COLOR
+                                    environment variable is set.
+        --set KEY=VALUE             Overrides a key of the config, can be repeated.
+        --print-config              Prints the config used for the first file, or the current
+                                    directory, with where each value comes from.
+        --exclude GLOB              Skip the files matching the glob, can be repeated. Added to
+                                    the `exclude` list of the config.
+        --verify-idempotent         Formats the inputs twice, reports the ones where the second
+                                    formatting differs from the first with a diff. Exits with 1
+                                    if there is one. Nothing is written.
         --verbose                   increase verbosity for non errors
         -v, --version               Prints the current version.
         -h, --help                  Prints this help.
-        -c, --config FILE           specify the path to the config file, defaults to typstfmt.toml
+        -c, --config FILE           specify the path to the config file, defaults to the closest
+                                    typstfmt.toml in the parent directories of each file, up to
+                                    the root of the repository.
         --get-global-config-path    Prints the path of the global configuration file.
         -C, --make-default-config   Create a default config file at typstfmt.toml
+        --lsp                       Run as a language server over stdio, providing document,
+                                    range and on type formatting.
 "#;
 
 enum Inputs {
@@ -42,86 +84,154 @@ struct Input {
 }
 
 impl Inputs {
-    fn read(&self) -> Box<dyn Iterator<Item = Input> + '_> {
+    /// replaces the directories by the typst files they contain, the files not
+    /// included by their config are left out, like the ones with errors.
+    fn expand_dirs(self, configs: &mut Configs, exit_code: &mut i32) -> Self {
+        let Inputs::Files(paths) = self else {
+            return self;
+        };
+        let mut files = vec![];
+        for path in paths {
+            if !Path::new(&path).is_dir() {
+                match configs.includes(Path::new(&path)) {
+                    Ok(true) => files.push(path),
+                    // it was named, skipping it silently would be surprising.
+                    Ok(false) => eprintln!("Warning: {path:?} is excluded by the config, skipped."),
+                    Err(e) => report_error(e, exit_code),
+                }
+                continue;
+            }
+            let walk = WalkBuilder::new(&path)
+                .require_git(false)
+                .add_custom_ignore_filename(IGNORE_FILE_NAME)
+                .sort_by_file_name(|a, b| a.cmp(b))
+                .build();
+            for entry in walk {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        report_error(
+                            CliError::Io(format!("Couldn't walk {path:?}: {err}")),
+                            exit_code,
+                        );
+                        continue;
+                    }
+                };
+                if !(entry.file_type().is_some_and(|t| t.is_file())
+                    && entry.path().extension().is_some_and(|ext| ext == "typ"))
+                {
+                    continue;
+                }
+                match configs.includes(entry.path()) {
+                    Ok(true) => files.push(entry.into_path().into_os_string()),
+                    Ok(false) => {}
+                    Err(e) => report_error(e, exit_code),
+                }
+            }
+        }
+        Inputs::Files(files)
+    }
+
+    fn read(&self) -> Box<dyn Iterator<Item = Result<Input, CliError>> + '_> {
         match self {
             Inputs::Stdin => {
                 let mut input_buf = String::new();
-                stdin()
+                let input = stdin()
                     .read_to_string(&mut input_buf)
-                    .expect("Couldn't read stdin.");
-                Box::new(std::iter::once(Input {
-                    name: "stdin".to_owned(),
-                    content: input_buf,
-                }))
+                    .map(|_| Input {
+                        name: "stdin".to_owned(),
+                        content: input_buf,
+                    })
+                    .map_err(|err| CliError::Io(format!("Couldn't read stdin: {err}")));
+                Box::new(std::iter::once(input))
             }
             Inputs::Files(paths) => Box::new(paths.iter().map(|path| {
                 let mut input_buf = String::new();
                 let mut file = File::options()
                     .read(true)
                     .open(path)
-                    .unwrap_or_else(|err| panic!("Failed to open file {path:?}: {err}"));
+                    .map_err(|err| CliError::Io(format!("Failed to open file {path:?}: {err}")))?;
                 file.read_to_string(&mut input_buf)
-                    .unwrap_or_else(|err| panic!("Couldn't read file {path:?}: {err}"));
-                Input {
+                    .map_err(|err| CliError::Io(format!("Couldn't read file {path:?}: {err}")))?;
+                Ok(Input {
                     name: path.to_string_lossy().into_owned(),
                     content: input_buf,
-                }
+                })
             })),
         }
     }
 }
 
+/// where the `--verbose` messages are printed, stderr when stdout has a report.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Verbose {
+    Off,
+    Stdout,
+    Stderr,
+}
+
+impl Verbose {
+    pub(crate) fn print(self, message: std::fmt::Arguments) {
+        match self {
+            Verbose::Off => {}
+            Verbose::Stdout => println!("{message}"),
+            Verbose::Stderr => eprintln!("{message}"),
+        }
+    }
+}
+
 enum Output {
-    None,
+    /// overwrites the inputs, keeping a copy of each one with the suffix if some.
+    None(Option<String>),
     Check,
+    /// like check, printing the changes, colored if true.
+    Diff(bool),
     Stdout,
     File(OsString),
 }
 
 impl Output {
-    fn write(&self, input: &Input, formatted: &str, verbose: bool) -> Result<(), ()> {
+    /// false if the input isn't formatted in check mode.
+    fn write(&self, input: &Input, formatted: &str, verbose: Verbose) -> Result<bool, CliError> {
         match self {
-            Output::None => {
+            Output::None(backup) => {
                 // this is not stdout by the check after parsing the arguments that sets the output
                 // to stdout rather than none for stdin.
                 let path = &input.name;
                 if formatted == input.content {
                     println!("file: {path:?} up to date.");
-                    return Ok(());
+                    return Ok(true);
                 }
-                let mut file = File::options()
-                    .create(true)
-                    .write(true)
-                    .truncate(true)
-                    .open(path)
-                    .unwrap_or_else(|err| panic!("Couldn't open file: {path:?}: {err}"));
-                file.write_all(formatted.as_bytes())
-                    .unwrap_or_else(|err| panic!("Failed to write to file {path:?}: {err}"));
-                if verbose {
-                    println!("file: {path:?} overwritten.");
-                };
+                write_atomically(Path::new(path), formatted, backup.as_deref()).map_err(|err| {
+                    CliError::Io(format!("Failed to write to file {path:?}: {err}"))
+                })?;
+                verbose.print(format_args!("file: {path:?} overwritten."));
             }
             Output::Check => {
                 if input.content != formatted {
-                    if verbose {
-                        println!("{} needs formatting.", input.name);
-                    }
-                    return Err(());
+                    verbose.print(format_args!("{} needs formatting.", input.name));
+                    return Ok(false);
                 } else {
-                    if verbose {
-                        println!("{} is already formatted.", input.name);
-                    }
+                    verbose.print(format_args!("{} is already formatted.", input.name));
                 }
             }
+            Output::Diff(color) => {
+                if input.content != formatted {
+                    print_diff(
+                        &input.content,
+                        formatted,
+                        (&input.name, &format!("{} (formatted)", input.name)),
+                        *color,
+                    );
+                    return Ok(false);
+                }
+                verbose.print(format_args!("{} is already formatted.", input.name));
+            }
             Output::Stdout => {
-                if verbose {
-                    println!("=== {:?} ===", input.name);
-                };
+                verbose.print(format_args!("=== {:?} ===", input.name));
                 stdout()
                     .write_all(formatted.as_bytes())
-                    .unwrap_or_else(|err| {
-                        panic!("Couldn't write to stdout: {}", err);
-                    });
+                    .map_err(|err| CliError::Io(format!("Couldn't write to stdout: {err}")))?;
             }
             Output::File(output) => {
                 let mut file = File::options()
@@ -129,37 +239,125 @@ impl Output {
                     .write(true)
                     .truncate(true)
                     .open(output.to_string_lossy().into_owned())
-                    .unwrap_or_else(|err| panic!("Couldn't create output file: {output:?}: {err}"));
+                    .map_err(|err| {
+                        CliError::Io(format!("Couldn't create output file: {output:?}: {err}"))
+                    })?;
 
-                file.write_all(formatted.as_bytes())
-                    .unwrap_or_else(|err| panic!("Couldn't write to file: {output:?}: {err}"));
+                file.write_all(formatted.as_bytes()).map_err(|err| {
+                    CliError::Io(format!("Couldn't write to file: {output:?}: {err}"))
+                })?;
             }
         }
-        Ok(())
+        Ok(true)
+    }
+}
+
+/// writes a temporary file next to the file then renames it over the file, so
+/// it's never left half written, the permissions of the file are kept.
+///
+/// With a backup suffix, the file is first copied to the given path with the
+/// suffix, replacing the previous backup.
+fn write_atomically(path: &Path, content: &str, backup: Option<&str>) -> io::Result<()> {
+    // the target of a symlink is replaced, not the symlink.
+    let target = fs::canonicalize(path)?;
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    name.push(format!(".{}.tmp", std::process::id()));
+    let tmp = target.with_file_name(name);
+    let write = || -> io::Result<()> {
+        let mut file = File::options().write(true).create_new(true).open(&tmp)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::set_permissions(&tmp, fs::metadata(&target)?.permissions())?;
+        if let Some(suffix) = backup {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(suffix);
+            fs::copy(&target, backup)?;
+        }
+        fs::rename(&tmp, &target)
+    };
+    let result = write();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
     }
+    result
 }
 
-fn main() -> Result<(), lexopt::Error> {
+/// prints the unified diff between two versions of a file, the removed lines
+/// in red and the added ones in green if `color`.
+fn print_diff(old: &str, new: &str, (old_name, new_name): (&str, &str), color: bool) {
+    let (bold, red, green, cyan, reset) = if color {
+        ("\x1b[1m", "\x1b[31m", "\x1b[32m", "\x1b[36m", "\x1b[0m")
+    } else {
+        ("", "", "", "", "")
+    };
+    println!("{bold}--- {old_name}{reset}");
+    println!("{bold}+++ {new_name}{reset}");
+    let diff = TextDiff::from_lines(old, new);
+    for hunk in diff.unified_diff().iter_hunks() {
+        println!("{cyan}{}{reset}", hunk.header());
+        for change in hunk.iter_changes() {
+            let (sign, color) = match change.tag() {
+                ChangeTag::Delete => ('-', red),
+                ChangeTag::Insert => ('+', green),
+                ChangeTag::Equal => (' ', ""),
+            };
+            let line = change.value().trim_end_matches(['\n', '\r']);
+            println!("{color}{sign}{line}{reset}");
+            if change.missing_newline() {
+                println!("\\ No newline at end of file");
+            }
+        }
+    }
+}
+
+/// prints the error, the exit code becomes the one of the error if it's higher.
+fn report_error(e: CliError, exit_code: &mut i32) {
+    eprintln!("Error: {e}");
+    *exit_code = (*exit_code).max(e.exit_code());
+}
+
+fn main() {
+    let exit_code = run().unwrap_or_else(|e| {
+        let mut exit_code = 0;
+        report_error(e, &mut exit_code);
+        exit_code
+    });
+    std::process::exit(exit_code);
+}
+
+/// formats the inputs, returns the exit code, the errors stopping everything
+/// are returned.
+fn run() -> Result<i32, CliError> {
     let mut parser = lexopt::Parser::from_env();
     let mut inputs = Inputs::Stdin;
-    let mut output = Output::None;
-    let mut config_file: OsString = CONFIG_FILE_NAME.into();
+    let mut output = Output::None(None);
+    let mut config_file = None;
     let mut verbose = false;
+    let mut verify_idempotent = false;
+    let mut exclude = vec![];
+    let mut set = String::new();
+    let mut print_config = false;
+    let mut color = std::env::var_os("NO_COLOR").is_none();
+    let mut report: Option<ReportFormat> = None;
+    let mut backup = None;
     while let Some(arg) = parser.next()? {
         match arg {
             Long("version") | Short('v') => {
                 println!("version: {VERSION}");
-                return Ok(());
+                return Ok(0);
             }
             Long("help") | Short('h') => {
                 println!("{HELP}");
-                return Ok(());
+                return Ok(0);
             }
             Long("get-global-config-path") => {
-                let config_path = confy::get_configuration_file_path("typstfmt", None)
-                    .unwrap_or_else(|e| panic!("Error loading global configuration file: {e}"));
+                let config_path =
+                    confy::get_configuration_file_path("typstfmt", None).map_err(|e| {
+                        CliError::Config(format!("Error loading global configuration file: {e}"))
+                    })?;
                 println!("{}", config_path.display());
-                return Ok(());
+                return Ok(0);
             }
             Long("make-default-config") | Short('C') => {
                 let s = Config::default_toml();
@@ -167,14 +365,18 @@ fn main() -> Result<(), lexopt::Error> {
                     .create_new(true)
                     .write(true)
                     .open(CONFIG_FILE_NAME)
-                    .unwrap_or_else(|e| {
-                        panic!("Couldn't create a new config file at {CONFIG_FILE_NAME}.\nCaused by {e}")
-                    });
-                f.write_all(s.as_bytes()).unwrap_or_else(|err| {
-                    panic!("Failed to write to file {CONFIG_FILE_NAME:?}: {err}")
-                });
+                    .map_err(|e| {
+                        CliError::Io(format!(
+                            "Couldn't create a new config file at {CONFIG_FILE_NAME}.\nCaused by {e}"
+                        ))
+                    })?;
+                f.write_all(s.as_bytes()).map_err(|err| {
+                    CliError::Io(format!(
+                        "Failed to write to file {CONFIG_FILE_NAME:?}: {err}"
+                    ))
+                })?;
                 println!("Created config file at: {CONFIG_FILE_NAME}");
-                return Ok(());
+                return Ok(0);
             }
             Value(v) => {
                 inputs = match inputs {
@@ -197,7 +399,7 @@ fn main() -> Result<(), lexopt::Error> {
                 };
             }
             Long("config") | Short('c') => {
-                config_file = parser.value()?;
+                config_file = Some(PathBuf::from(parser.value()?));
             }
             Long("verbose") => {
                 verbose = true;
@@ -205,67 +407,182 @@ fn main() -> Result<(), lexopt::Error> {
             Long("check") => {
                 output = Output::Check;
             }
-            _ => {
-                println!("{}", arg.unexpected());
-                println!("use -h or --help");
-                return Ok(());
+            Long("report-format") => {
+                report = Some(parser.value()?.parse()?);
+            }
+            Long("backup") => {
+                let suffix = match parser.optional_value() {
+                    Some(suffix) => suffix.string()?,
+                    None => DEFAULT_BACKUP_SUFFIX.to_owned(),
+                };
+                backup = Some(suffix);
+            }
+            Long("diff") => {
+                output = Output::Diff(true);
+            }
+            Long("no-color") => {
+                color = false;
+            }
+            Long("set") => {
+                let option = parser.value()?.string()?;
+                let line = config::set_to_toml(&option)
+                    .map_err(|e| CliError::Config(format!("Invalid --set option: {e}")))?;
+                set.push_str(&line);
+                set.push('\n');
+            }
+            Long("print-config") => {
+                print_config = true;
+            }
+            Long("exclude") => {
+                exclude.push(parser.value()?.string()?);
+            }
+            Long("verify-idempotent") => {
+                verify_idempotent = true;
             }
+            Long("lsp") => {
+                lsp::run().map_err(|e| CliError::Io(format!("Language server failed: {e}")))?;
+                return Ok(0);
+            }
+            _ => return Err(arg.unexpected().into()),
         }
     }
 
-    if matches!(inputs, Inputs::Stdin) && matches!(output, Output::None) {
+    if report.is_some() {
+        // the report is printed on stdout and there is nothing to report
+        // about the files that are written.
+        if !matches!(output, Output::None(_) | Output::Check) || backup.is_some() {
+            return Err(CliError::Config(
+                "--report-format implies --check, it can't be used with --diff, --output, \
+                 --stdout or --backup."
+                    .to_owned(),
+            ));
+        }
+        output = Output::Check;
+    }
+    if matches!(inputs, Inputs::Stdin) && matches!(output, Output::None(_)) {
         output = Output::Stdout;
     }
+    if let Output::Diff(diff_color) = &mut output {
+        *diff_color = color;
+    }
+    if let Output::None(none_backup) = &mut output {
+        *none_backup = backup;
+    }
 
-    let config = {
-        if let Ok(mut f) = File::options().read(true).open(config_file.clone()) {
-            if verbose {
-                println!("Using the config file {config_file:?}");
-            }
-            let mut buf = String::default();
-            f.read_to_string(&mut buf)
-                .unwrap_or_else(|err| panic!("Failed to read config file {config_file:?}: {err}"));
-            Config::from_toml(&buf).unwrap_or_else(|e| panic!("Config file invalid: {e}.\nYou'll maybe have to delete it and use -C to create a default config file."))
-        } else {
-            let config_path = confy::get_configuration_file_path("typstfmt", None)
-                .unwrap_or_else(|e| panic!("Error loading global configuration file: {e}"));
-            if verbose {
-                println!("Using the config file {config_path:?}");
-            }
-            confy::load("typstfmt", None).unwrap_or_else(|e| {
-                panic!(
-                    "Error loading global configuration file at {}: {e}",
-                    config_path.display()
-                )
-            })
-        }
+    if let Some(file) = config_file.as_ref().filter(|file| !file.is_file()) {
+        return Err(CliError::Config(format!(
+            "The config file {file:?} given with --config doesn't exist."
+        )));
+    }
+    let verbose = match (verbose, report) {
+        (false, _) => Verbose::Off,
+        (true, None) => Verbose::Stdout,
+        (true, Some(_)) => Verbose::Stderr,
     };
+    let mut configs = Configs::new(config_file, exclude, set, verbose);
 
-    let mut exit_status = 0;
+    if print_config {
+        let path = match &inputs {
+            Inputs::Files(paths) => Path::new(&paths[0]),
+            Inputs::Stdin => Path::new("."),
+        };
+        configs.print(path)?;
+        return Ok(0);
+    }
 
-    match &inputs {
-        Inputs::Stdin => {}
-        Inputs::Files(paths) => {
-            assert!(
-                !(matches!(output, Output::File(_)) && paths.len() > 1),
-                "You specified multiple inputs and --output but one output file cannot receive the result of many files.\nAborting."
-    );
+    let mut exit_code = 0;
+    let mut unformatted = vec![];
+
+    let inputs = inputs.expand_dirs(&mut configs, &mut exit_code);
+    if let Inputs::Files(paths) = &inputs {
+        if matches!(output, Output::File(_)) && paths.len() > 1 {
+            return Err(CliError::Config(
+                "You specified multiple inputs and --output but one output file cannot receive \
+                 the result of many files.\nAborting."
+                    .to_owned(),
+            ));
         }
     }
 
     for input in inputs.read() {
-        let formatted = format(&input.content, config);
+        let input = match input {
+            Ok(input) => input,
+            Err(e) => {
+                report_error(e, &mut exit_code);
+                continue;
+            }
+        };
+        let path = match &inputs {
+            Inputs::Stdin => Path::new("."),
+            Inputs::Files(_) => Path::new(&input.name),
+        };
+        let config = match configs.for_path(path) {
+            Ok(config) => config,
+            Err(e) => {
+                report_error(e, &mut exit_code);
+                continue;
+            }
+        };
+        let mut formatted = format(&input.content, config.clone());
+        if !verify(&input.content, &formatted) {
+            report_error(
+                CliError::Bug(format!(
+                    "Formatting {} changed its meaning, it was left as is.\nThis is a bug, please report it at {ISSUES} with the file.",
+                    input.name
+                )),
+                &mut exit_code,
+            );
+            if report.is_some() {
+                unformatted.push(Unformatted::new(
+                    &input.name,
+                    &input.content,
+                    &formatted,
+                    report::BUG_MESSAGE,
+                ));
+            }
+            formatted = input.content.clone();
+        }
+
+        if verify_idempotent {
+            let formatted_twice = format(&formatted, config);
+            if formatted_twice != formatted {
+                println!("{} changes when formatted a second time:", input.name);
+                print_diff(
+                    &formatted,
+                    &formatted_twice,
+                    ("formatted once", "formatted twice"),
+                    color,
+                );
+                exit_code = exit_code.max(NEEDS_FORMATTING);
+            } else {
+                verbose.print(format_args!("{} is formatted the same twice.", input.name));
+            }
+            continue;
+        }
 
         match output.write(&input, &formatted, verbose) {
-            Ok(()) => {}
-            Err(()) => {
-                exit_status = 1;
+            Ok(true) => {}
+            Ok(false) => {
+                if report.is_some() {
+                    unformatted.push(Unformatted::new(
+                        &input.name,
+                        &input.content,
+                        &formatted,
+                        report::MESSAGE,
+                    ));
+                }
+                exit_code = exit_code.max(NEEDS_FORMATTING);
             }
+            Err(e) => report_error(e, &mut exit_code),
         }
     }
-    if exit_status == 0 {
-        Ok(())
-    } else {
-        std::process::exit(exit_status);
+    if let Some(report) = report {
+        report
+            .write(&unformatted, &mut stdout())
+            .map_err(|err| CliError::Io(format!("Couldn't write the report: {err}")))?;
     }
+    Ok(exit_code)
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,125 @@
+//! Machine readable reports of the files `--check` finds unformatted, for CI
+//! dashboards and annotations on pull requests.
+
+use std::io::{self, Write};
+
+use serde_json::json;
+
+pub(crate) const MESSAGE: &str = "File is not formatted, run typstfmt on it.";
+pub(crate) const BUG_MESSAGE: &str =
+    "Formatting changed the meaning of the file, it was left as is. This is a bug of typstfmt.";
+
+#[derive(Clone, Copy)]
+pub(crate) enum ReportFormat {
+    Json,
+    Checkstyle,
+    /// workflow commands annotating the lines in github actions.
+    Github,
+}
+
+impl std::str::FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ReportFormat::Json),
+            "checkstyle" => Ok(ReportFormat::Checkstyle),
+            "github" => Ok(ReportFormat::Github),
+            _ => Err(format!(
+                "unknown report format {s:?}, expected json, checkstyle or github"
+            )),
+        }
+    }
+}
+
+/// A file that isn't formatted.
+pub(crate) struct Unformatted {
+    file: String,
+    /// the first line formatting changes, from 1, the last line of the file
+    /// when it only adds lines after it.
+    line: usize,
+    message: &'static str,
+}
+
+impl Unformatted {
+    pub(crate) fn new(file: &str, content: &str, formatted: &str, message: &'static str) -> Self {
+        let (lines, formatted_lines) = (content.split('\n'), formatted.split('\n'));
+        // a line past the end of the file would be refused by some tools.
+        let last = content.lines().count().saturating_sub(1);
+        let line = lines
+            .zip(formatted_lines)
+            .position(|(a, b)| a != b)
+            .unwrap_or(last)
+            .min(last);
+        Self {
+            file: file.to_owned(),
+            line: line + 1,
+            message,
+        }
+    }
+}
+
+impl ReportFormat {
+    /// writes the report of all the unformatted files, the json and checkstyle
+    /// reports are written even if there is none.
+    pub(crate) fn write(self, unformatted: &[Unformatted], out: &mut impl Write) -> io::Result<()> {
+        match self {
+            ReportFormat::Json => {
+                let records: Vec<_> = unformatted
+                    .iter()
+                    .map(|u| json!({"file": u.file, "line": u.line, "message": u.message}))
+                    .collect();
+                writeln!(out, "{}", serde_json::Value::Array(records))?;
+            }
+            ReportFormat::Checkstyle => {
+                writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+                writeln!(out, r#"<checkstyle version="4.3">"#)?;
+                for u in unformatted {
+                    writeln!(out, r#"<file name="{}">"#, escape_xml(&u.file))?;
+                    writeln!(
+                        out,
+                        r#"<error line="{}" severity="error" message="{}" source="typstfmt"/>"#,
+                        u.line,
+                        escape_xml(u.message)
+                    )?;
+                    writeln!(out, "</file>")?;
+                }
+                writeln!(out, "</checkstyle>")?;
+            }
+            ReportFormat::Github => {
+                for u in unformatted {
+                    writeln!(
+                        out,
+                        "::error file={},line={}::{}",
+                        escape_github(&u.file, true),
+                        u.line,
+                        escape_github(u.message, false)
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// escapes the data of a workflow command, properties also escape their
+/// separators.
+fn escape_github(s: &str, property: bool) -> String {
+    let s = s
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    if property {
+        s.replace(':', "%3A").replace(',', "%2C")
+    } else {
+        s
+    }
+}
@@ -0,0 +1,150 @@
+use std::fs;
+
+use super::common::dir;
+use crate::{config::*, Verbose};
+
+/// the configs without the global config of the user.
+fn configs() -> Configs {
+    let mut configs = Configs::new(None, vec![], String::new(), Verbose::Off);
+    configs.global = None;
+    configs
+}
+
+#[test]
+fn the_closest_config_is_used() {
+    let dir = dir("closest");
+    fs::create_dir(dir.join("nested")).unwrap();
+    fs::write(dir.join("typstfmt.toml"), "max_line_length = 100").unwrap();
+    fs::write(dir.join("nested/typstfmt.toml"), "max_line_length = 60").unwrap();
+    let mut configs = configs();
+    let nested = configs.for_path(&dir.join("nested/main.typ")).unwrap();
+    assert_eq!(nested.max_line_length, 60);
+    let root = configs.for_path(&dir.join("main.typ")).unwrap();
+    assert_eq!(root.max_line_length, 100);
+}
+
+#[test]
+fn configs_are_read_again_when_they_change() {
+    let dir = dir("reload");
+    let file = dir.join("main.typ");
+    let mut configs = configs();
+    fs::write(dir.join("typstfmt.toml"), "max_line_length = 60").unwrap();
+    assert_eq!(configs.for_path(&file).unwrap().max_line_length, 60);
+    fs::write(dir.join("typstfmt.toml"), "max_line_length = 120").unwrap();
+    assert_eq!(configs.for_path(&file).unwrap().max_line_length, 120);
+    fs::remove_file(dir.join("typstfmt.toml")).unwrap();
+    assert_eq!(configs.for_path(&file).unwrap().max_line_length, 80);
+}
+
+#[test]
+fn set_options_are_toml() {
+    assert_eq!(
+        set_to_toml("max_line_length=100").unwrap(),
+        "max_line_length = 100"
+    );
+    assert_eq!(
+        set_to_toml(" line_wrap = false ").unwrap(),
+        "line_wrap = false"
+    );
+    // a value that isn't valid toml is a string.
+    assert_eq!(
+        set_to_toml("line_wrap=sentence").unwrap(),
+        r#"line_wrap = "sentence""#
+    );
+    assert_eq!(set_to_toml("a.b=c").unwrap(), r#"a.b = "c""#);
+    assert_eq!(set_to_toml("a=b=c").unwrap(), r#"a = "b=c""#);
+    assert!(set_to_toml("max_line_length").is_err());
+}
+
+#[test]
+fn layers_override_the_keys_they_set() {
+    let dir = dir("layers");
+    let global = dir.join("global.toml");
+    fs::write(
+        &global,
+        "max_line_length = 60\nindent_space = 4\nline_wrap = false",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("typstfmt.toml"),
+        "max_line_length = 100\nindent_space = 3",
+    )
+    .unwrap();
+    let set = set_to_toml("indent_space=1").unwrap();
+    let mut configs = Configs::new(None, vec![], set, Verbose::Off);
+    configs.global = Some(global);
+    let config = configs.for_path(&dir.join("main.typ")).unwrap();
+    assert_eq!(config.line_wrap, typstfmt_lib::LineWrap::Off);
+    assert_eq!(config.max_line_length, 100);
+    assert_eq!(config.indent_space, 1);
+}
+
+#[test]
+fn tool_table_of_a_manifest() {
+    let manifest = "[package]\nname = \"a\"\n\n[tool.typstfmt]\nmax_line_length = 90\n";
+    let config = typstfmt_lib::Config::from_toml(&tool_table(manifest).unwrap()).unwrap();
+    assert_eq!(config.max_line_length, 90);
+    assert_eq!(tool_table("[package]\nname = \"a\"\n"), None);
+    assert_eq!(tool_table("[tool.other]\na = 1\n"), None);
+}
+
+#[test]
+fn manifests_with_a_tool_table_are_project_configs() {
+    let dir = dir("manifest");
+    fs::create_dir_all(dir.join("package/src")).unwrap();
+    fs::create_dir_all(dir.join("other")).unwrap();
+    fs::write(dir.join("typstfmt.toml"), "max_line_length = 100").unwrap();
+    fs::write(
+        dir.join("package/typst.toml"),
+        "[package]\nname = \"a\"\n\n[tool.typstfmt]\nmax_line_length = 90\n",
+    )
+    .unwrap();
+    // without the table, the manifest is skipped.
+    fs::write(dir.join("other/typst.toml"), "[package]\nname = \"b\"\n").unwrap();
+    let mut configs = configs();
+    let package = configs.for_path(&dir.join("package/src/main.typ")).unwrap();
+    assert_eq!(package.max_line_length, 90);
+    let other = configs.for_path(&dir.join("other/main.typ")).unwrap();
+    assert_eq!(other.max_line_length, 100);
+}
+
+#[test]
+fn editorconfig_properties() {
+    let dir = dir("editorconfig");
+    fs::write(
+        dir.join(".editorconfig"),
+        "root = true\n\n[*.typ]\nindent_style = tab\nindent_size = 2\nend_of_line = crlf\n\
+         max_line_length = off\ninsert_final_newline = true\n",
+    )
+    .unwrap();
+    let table: toml::Table = editorconfig_toml(&dir.join("main.typ"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(table["indent_style"].as_str(), Some("tab"));
+    assert_eq!(table["indent_space"].as_integer(), Some(2));
+    assert_eq!(table["newline_style"].as_str(), Some("crlf"));
+    assert_eq!(table["insert_final_newline"].as_bool(), Some(true));
+    assert!(!table.contains_key("max_line_length"));
+    // the section doesn't match other files.
+    assert_eq!(editorconfig_toml(&dir.join("main.md")).unwrap(), "");
+}
+
+#[test]
+fn editorconfig_is_the_first_layer() {
+    let dir = dir("editorconfig-layer");
+    fs::write(
+        dir.join(".editorconfig"),
+        "root = true\n\n[*]\nindent_size = 2\nmax_line_length = 50\nend_of_line = crlf\n",
+    )
+    .unwrap();
+    let global = dir.join("global.toml");
+    fs::write(&global, "indent_space = 4").unwrap();
+    fs::write(dir.join("typstfmt.toml"), "max_line_length = 100").unwrap();
+    let mut configs = configs();
+    configs.global = Some(global);
+    let config = configs.for_path(&dir.join("main.typ")).unwrap();
+    assert_eq!(config.indent_space, 4);
+    assert_eq!(config.max_line_length, 100);
+    assert_eq!(config.newline_style, typstfmt_lib::NewlineStyle::Crlf);
+}
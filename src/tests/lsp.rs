@@ -0,0 +1,59 @@
+use lsp_types::Position;
+
+use crate::lsp::*;
+
+#[test]
+fn offsets_count_utf16_units() {
+    // `é` is 2 bytes and 1 unit, `𝒜` is 4 bytes and 2 units.
+    let text = "é𝒜a\nb";
+    assert_eq!(offset(text, Position::new(0, 0)), 0);
+    assert_eq!(offset(text, Position::new(0, 1)), 2);
+    assert_eq!(offset(text, Position::new(0, 3)), 6);
+    assert_eq!(offset(text, Position::new(0, 4)), 7);
+    assert_eq!(offset(text, Position::new(1, 1)), 9);
+}
+
+#[test]
+fn offsets_after_the_end_of_the_line() {
+    let text = "ab\r\ncd\ne";
+    assert_eq!(offset(text, Position::new(0, 2)), 2);
+    assert_eq!(offset(text, Position::new(0, 10)), 2);
+    assert_eq!(offset(text, Position::new(1, 0)), 4);
+    assert_eq!(offset(text, Position::new(1, 10)), 6);
+    assert_eq!(offset(text, Position::new(2, 10)), 8);
+    assert_eq!(offset(text, Position::new(5, 0)), 8);
+}
+
+#[test]
+fn positions_count_utf16_units() {
+    let text = "é𝒜a\r\nb";
+    assert_eq!(position(text, 0), Position::new(0, 0));
+    assert_eq!(position(text, 2), Position::new(0, 1));
+    assert_eq!(position(text, 6), Position::new(0, 3));
+    assert_eq!(position(text, 7), Position::new(0, 4));
+    assert_eq!(position(text, 9), Position::new(1, 0));
+    assert_eq!(position(text, 10), Position::new(1, 1));
+}
+
+#[test]
+fn positions_and_offsets_round_trip() {
+    let text = "#f(a,\r\n  𝒜)\r\n\r\né";
+    for (offset_, _) in text
+        .char_indices()
+        .filter(|(idx, _)| !text[..*idx].ends_with('\r'))
+    {
+        assert_eq!(offset(text, position(text, offset_)), offset_);
+    }
+}
+
+#[test]
+fn edits_are_applied() {
+    let edits = [(1..2, "x".to_owned()), (3..3, "yz".to_owned())];
+    assert_eq!(apply("abcd", &edits), "axcyzd");
+}
+
+#[test]
+fn formatting_changing_the_meaning_is_refused() {
+    assert!(check_meaning("some words", "some  words").is_ok());
+    assert!(check_meaning("some words", "somewords").is_err());
+}
@@ -0,0 +1,5 @@
+#[path = "../../tests/common/mod.rs"]
+mod common;
+mod config;
+mod lsp;
+mod report;
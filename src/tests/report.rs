@@ -0,0 +1,73 @@
+use crate::report::*;
+
+fn report(format: ReportFormat, unformatted: &[Unformatted]) -> String {
+    let mut out = vec![];
+    format.write(unformatted, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn lines_are_in_the_file() {
+    let line = |content, formatted| {
+        let unformatted = Unformatted::new("a.typ", content, formatted, MESSAGE);
+        let report = report(ReportFormat::Json, &[unformatted]);
+        let records: serde_json::Value = serde_json::from_str(&report).unwrap();
+        records[0]["line"].as_u64().unwrap()
+    };
+    assert_eq!(line("a\nb\nc\n", "a\nB\nc\n"), 2);
+    assert_eq!(line("a\nb", "a\nb\n"), 2);
+    assert_eq!(line("a\n", "a\n\n\n"), 1);
+    assert_eq!(line("", "\n"), 1);
+}
+
+#[test]
+fn json_report() {
+    let unformatted = [
+        Unformatted::new("a \"b\".typ", "#f( )\n", "#f()\n", MESSAGE),
+        Unformatted::new("c.typ", "a\n", "a\n", BUG_MESSAGE),
+    ];
+    let records: serde_json::Value =
+        serde_json::from_str(&report(ReportFormat::Json, &unformatted)).unwrap();
+    assert_eq!(
+        records,
+        serde_json::json!([
+            {"file": "a \"b\".typ", "line": 1, "message": MESSAGE},
+            {"file": "c.typ", "line": 1, "message": BUG_MESSAGE},
+        ])
+    );
+    assert_eq!(report(ReportFormat::Json, &[]), "[]\n");
+}
+
+#[test]
+fn checkstyle_report() {
+    let unformatted = [Unformatted::new(
+        "a&<b>.typ",
+        "a\n#f( )\n",
+        "a\n#f()\n",
+        MESSAGE,
+    )];
+    assert_eq!(
+        report(ReportFormat::Checkstyle, &unformatted),
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <checkstyle version=\"4.3\">\n\
+             <file name=\"a&amp;&lt;b&gt;.typ\">\n\
+             <error line=\"2\" severity=\"error\" message=\"{MESSAGE}\" source=\"typstfmt\"/>\n\
+             </file>\n\
+             </checkstyle>\n"
+        )
+    );
+    assert_eq!(
+        report(ReportFormat::Checkstyle, &[]),
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<checkstyle version=\"4.3\">\n</checkstyle>\n"
+    );
+}
+
+#[test]
+fn github_report() {
+    let unformatted = [Unformatted::new("a,b:c.typ", "#f( )\n", "#f()\n", MESSAGE)];
+    assert_eq!(
+        report(ReportFormat::Github, &unformatted),
+        format!("::error file=a%2Cb%3Ac.typ,line=1::{MESSAGE}\n")
+    );
+}
@@ -0,0 +1,274 @@
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+use common::dir;
+
+mod common;
+
+/// the formatter removes the space before the comment, the link then takes
+/// the `//` and the comment becomes text, replace it if this stops being a bug.
+const CHANGES_MEANING: &str = "See https://typst.app. // a comment\n";
+
+/// the spaces of a blank line are only removed by the second formatting,
+/// replace it if this stops being a bug.
+const NOT_IDEMPOTENT: &str = "a\n\n  \n\nb\n";
+
+fn typstfmt(args: &[&str], file: &PathBuf) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_typstfmt"))
+        .args(args)
+        .arg(file)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn changing_the_meaning_is_reported_in_every_mode() {
+    let dir = dir("changing-the-meaning");
+    let file = dir.join("a.typ");
+    fs::write(&file, CHANGES_MEANING).unwrap();
+    for args in [&[][..], &["--check"], &["--diff"], &["--output", "-"]] {
+        let output = typstfmt(args, &file);
+        assert_eq!(output.status.code(), Some(1), "{args:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("changed its meaning"), "{args:?}: {stderr}");
+        assert_eq!(fs::read_to_string(&file).unwrap(), CHANGES_MEANING);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn changing_the_meaning_is_in_the_report() {
+    let dir = dir("changing-the-meaning-report");
+    let file = dir.join("a.typ");
+    fs::write(&file, CHANGES_MEANING).unwrap();
+    let output = typstfmt(&["--report-format", "json"], &file);
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report[0]["file"], file.to_str().unwrap());
+    assert_eq!(report[0]["line"], 1);
+    assert!(report[0]["message"]
+        .as_str()
+        .unwrap()
+        .contains("This is a bug of typstfmt."));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn excluded_files_named_are_skipped_with_a_warning() {
+    let dir = dir("excluded");
+    let file = dir.join("a.typ");
+    fs::write(&file, "#f(a,b)").unwrap();
+    fs::write(dir.join("typstfmt.toml"), "exclude = [\"a.typ\"]").unwrap();
+    let output = typstfmt(&[], &file);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is excluded by the config"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "#f(a,b)");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn directories_are_walked_respecting_the_ignore_files() {
+    let dir = dir("walk");
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::create_dir_all(dir.join("skipped")).unwrap();
+    fs::write(dir.join(".gitignore"), "ignored.typ\n").unwrap();
+    fs::write(dir.join(".typstfmtignore"), "skipped/\n").unwrap();
+    let files = [
+        "a.typ",
+        "sub/b.typ",
+        "ignored.typ",
+        "skipped/c.typ",
+        "d.txt",
+    ];
+    for file in files {
+        fs::write(dir.join(file), "#f(a,b)").unwrap();
+    }
+    let output = typstfmt(&[], &dir);
+    assert_eq!(output.status.code(), Some(0));
+    for (file, formatted) in files.into_iter().zip([true, true, false, false, false]) {
+        let expected = if formatted { "#f(a, b)" } else { "#f(a,b)" };
+        assert_eq!(
+            fs::read_to_string(dir.join(file)).unwrap(),
+            expected,
+            "{file}"
+        );
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reports_only_go_with_check() {
+    let dir = dir("report-with-diff");
+    let file = dir.join("a.typ");
+    fs::write(&file, "#f(a,b)").unwrap();
+    for args in [&["--diff"][..], &["--output", "-"], &["--backup"]] {
+        let args = [args, &["--report-format", "json"]].concat();
+        let output = typstfmt(&args, &file);
+        assert_eq!(output.status.code(), Some(3), "{args:?}");
+        assert!(output.stdout.is_empty(), "{args:?}");
+    }
+    assert_eq!(fs::read_to_string(&file).unwrap(), "#f(a,b)");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn verbose_messages_stay_out_of_the_report() {
+    let dir = dir("report-verbose");
+    let file = dir.join("a.typ");
+    fs::write(&file, "#f(a,b)").unwrap();
+    let output = typstfmt(&["--verbose", "--report-format", "json"], &file);
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report[0]["line"], 1);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("needs formatting"));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn missing_config_files_are_invalid_options() {
+    let dir = dir("missing-config");
+    let file = dir.join("a.typ");
+    fs::write(&file, "#f(a,b)").unwrap();
+    let config = dir.join("missing.toml");
+    let output = typstfmt(&["--config", config.to_str().unwrap()], &file);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("doesn't exist"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "#f(a,b)");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unreadable_files_dont_stop_the_others() {
+    let dir = dir("unreadable");
+    let unreadable = dir.join("a.typ");
+    fs::write(&unreadable, b"#f(a,b) \xff").unwrap();
+    let file = dir.join("b.typ");
+    fs::write(&file, "#f(a,b)").unwrap();
+    let output = typstfmt(&[unreadable.to_str().unwrap()], &file);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Couldn't read file"));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "#f(a, b)");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn formatting_twice_differently_is_reported_with_a_diff() {
+    let dir = dir("idempotent");
+    let file = dir.join("a.typ");
+    fs::write(&file, NOT_IDEMPOTENT).unwrap();
+    let output = typstfmt(&["--verify-idempotent", "--no-color"], &file);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "{} changes when formatted a second time:\n--- formatted once\n+++ formatted twice\n@@ -1,3 +1,3 @@\n a\n \n- b\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n",
+            file.display()
+        )
+    );
+    assert_eq!(fs::read_to_string(&file).unwrap(), NOT_IDEMPOTENT);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn diffs_without_colors_leave_the_file() {
+    let dir = dir("diff");
+    let file = dir.join("a.typ");
+    fs::write(&file, "#f(a,b)\nc").unwrap();
+    let output = typstfmt(&["--diff", "--no-color"], &file);
+    assert_eq!(output.status.code(), Some(1));
+    let name = file.display();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "--- {name}\n+++ {name} (formatted)\n@@ -1,2 +1,2 @@\n-#f(a,b)\n+#f(a, b)\n c\n\\ No newline at end of file\n"
+        )
+    );
+    assert_eq!(fs::read_to_string(&file).unwrap(), "#f(a,b)\nc");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn trailing_spaces_in_comments_and_raw_are_kept() {
+    let dir = dir("trailing-spaces");
+    let file = dir.join("a.typ");
+    let content = "#let a = 1 // a comment \n```\nb  \n```\n";
+    fs::write(&file, content).unwrap();
+    let output = typstfmt(&["--check"], &file);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn files_are_replaced_keeping_their_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = dir("replaced");
+    let file = dir.join("a.typ");
+    fs::write(&file, "#f(a,b)").unwrap();
+    fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+    let output = typstfmt(&[], &file);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "#f(a, b)");
+    let mode = fs::metadata(&file).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o640);
+    // the temporary file was renamed.
+    let files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(files.len(), 2, "{files:?}");
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn backups_replace_the_previous_one() {
+    let dir = dir("backup");
+    let file = dir.join("a.typ");
+    fs::write(&file, "#f(a,b)").unwrap();
+    fs::write(dir.join("a.typ.bak"), "an older backup").unwrap();
+    assert_eq!(typstfmt(&["--backup"], &file).status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&file).unwrap(), "#f(a, b)");
+    assert_eq!(
+        fs::read_to_string(dir.join("a.typ.bak")).unwrap(),
+        "#f(a,b)"
+    );
+    fs::write(&file, "#g(c,d)").unwrap();
+    assert_eq!(typstfmt(&["--backup=.orig"], &file).status.code(), Some(0));
+    assert_eq!(
+        fs::read_to_string(dir.join("a.typ.orig")).unwrap(),
+        "#g(c,d)"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("a.typ.bak")).unwrap(),
+        "#f(a,b)"
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn backups_of_symlinks_are_next_to_the_link() {
+    let dir = dir("backup-symlink");
+    fs::create_dir(dir.join("target")).unwrap();
+    let target = dir.join("target/a.typ");
+    fs::write(&target, "#f(a,b)").unwrap();
+    let link = dir.join("link.typ");
+    std::os::unix::fs::symlink(&target, &link).unwrap();
+    assert_eq!(typstfmt(&["--backup"], &link).status.code(), Some(0));
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "#f(a, b)");
+    assert_eq!(
+        fs::read_to_string(dir.join("link.typ.bak")).unwrap(),
+        "#f(a,b)"
+    );
+    assert!(!dir.join("target/a.typ.bak").exists());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,11 @@
+//! Helpers shared by the command line tests and the tests of the binary.
+
+use std::{fs, path::PathBuf};
+
+/// a new directory for the files of a test, at the root of a repository.
+pub fn dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("typstfmt-{}-{test}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join(".git")).unwrap();
+    dir
+}